@@ -0,0 +1,53 @@
+//! Run the gateway with an extra sink type
+//!
+//! Registers a `stdout` sink next to the built-in ones and runs the pipeline
+//! on simulated telemetry. Enable it in config.toml with:
+//!
+//! ```toml
+//! [[sinks]]
+//! type = "stdout"
+//! ```
+//!
+//! Run with: cargo run --example custom_sink
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+use wk7_mqtt_influx::config::Config;
+use wk7_mqtt_influx::fanout::SinkEvent;
+use wk7_mqtt_influx::gateway::{self, Source};
+use wk7_mqtt_influx::sink::{self, SinkRegistry, TelemetrySink};
+
+/// Prints every reading as one line
+struct StdoutSink;
+
+#[async_trait]
+impl TelemetrySink for StdoutSink {
+    async fn write(&mut self, event: &SinkEvent) -> Result<()> {
+        if let SinkEvent::Telemetry { readings, .. } = event {
+            for reading in readings {
+                println!("{}: {}", reading.node_id, reading);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt().with_env_filter("info").init();
+
+    let config = Config::load("config.toml")?;
+
+    let mut registry = SinkRegistry::builtin();
+    registry.register("stdout", |config, _| {
+        sink::no_options(config)?;
+        Ok(Box::new(StdoutSink))
+    });
+
+    let source = Source::Simulate {
+        interval: Duration::from_secs(1),
+        count: Some(10),
+    };
+    gateway::run(config, registry, source, None).await
+}
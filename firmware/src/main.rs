@@ -43,6 +43,7 @@ mod app {
 
     const NETWORK_ID: u8 = 18; // LoRa network ID
     const LORA_FREQ: u32 = 915; // LoRa frequency in MHz (915 for US)
+    const JSON_SCHEMA_VERSION: u8 = 2; // "v" field of the VCP JSON (must match gateway)
 
     // --- Binary Protocol Data Structures ---
     use serde::{Deserialize, Serialize};
@@ -733,7 +734,9 @@ mod app {
         let mut json = heapless::String::<512>::new();
 
         // Start JSON object (compact format to fit in USB buffer)
-        let _ = write!(json, "{{\"ts\":{},", timestamp_ms);
+        // "v" is the schema version; bump it when keys change meaning
+        let _ = write!(json, "{{\"v\":{},", JSON_SCHEMA_VERSION);
+        let _ = write!(json, "\"ts\":{},", timestamp_ms);
         let _ = write!(json, "\"id\":\"N2\",");
 
         // Node 1 sensor data (remote sensor via LoRa) - use short keys
@@ -742,6 +745,7 @@ mod app {
         let gas = parsed.sensor_data.gas_resistance;
 
         let _ = write!(json, "\"n1\":{{");
+        let _ = write!(json, "\"seq\":{},", parsed.sensor_data.packet_num); // LoRa sequence number (v2)
         let _ = write!(json, "\"t\":{:.1},", temp);
         let _ = write!(json, "\"h\":{:.1},", hum);
         let _ = write!(json, "\"g\":{}", gas);
@@ -0,0 +1,190 @@
+//! Windowed aggregation before writing to InfluxDB
+//!
+//! Collapses each node's metrics over fixed wall-clock windows (aligned to
+//! multiples of `window_secs` since the epoch) into min/max/mean/last/count.
+//! A window is closed by the first packet after it ends, by the processor's
+//! periodic check, or at shutdown. MQTT keeps getting every raw value.
+//!
+//! Values flagged bad by validation are left out of the aggregates.
+
+use crate::telemetry::{Quality, Reading};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch (windows use wall-clock time)
+pub fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Summary of one metric over a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub last: f64,
+    pub count: u64,
+}
+
+impl Aggregate {
+    fn new(value: f64) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value,
+            last: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.last = value;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// Aggregate of one node's metric
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub node: String,
+    pub metric: String,
+    pub unit: Option<String>,
+    pub aggregate: Aggregate,
+}
+
+/// A closed window, ready to be written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// Start of the window, in seconds since the epoch
+    pub start_secs: u64,
+    pub length_secs: u64,
+    pub series: Vec<Series>,
+}
+
+/// Open window being filled
+struct Open {
+    start_secs: u64,
+    length_secs: u64,
+    series: BTreeMap<(String, String), (Option<String>, Aggregate)>,
+}
+
+impl Open {
+    fn close(self) -> Window {
+        Window {
+            start_secs: self.start_secs,
+            length_secs: self.length_secs,
+            series: self
+                .series
+                .into_iter()
+                .map(|((node, metric), (unit, aggregate))| Series {
+                    node,
+                    metric,
+                    unit,
+                    aggregate,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Aggregation state, one per pipeline
+#[derive(Default)]
+pub struct Aggregator {
+    open: Option<Open>,
+}
+
+impl Aggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a packet's readings received at `now_secs`
+    ///
+    /// Returns the previous window if this packet starts a new one.
+    pub fn add(&mut self, readings: &[Reading], now_secs: u64, window_secs: u64) -> Option<Window> {
+        let closed = self.flush(now_secs, window_secs);
+
+        let start_secs = now_secs - now_secs % window_secs;
+        let open = self.open.get_or_insert_with(|| Open {
+            start_secs,
+            length_secs: window_secs,
+            series: BTreeMap::new(),
+        });
+        for reading in readings {
+            for metric in &reading.metrics {
+                if metric.quality == Some(Quality::Bad) || !metric.value.is_finite() {
+                    continue;
+                }
+                open.series
+                    .entry((reading.node_id.clone(), metric.name.clone()))
+                    .and_modify(|(_, aggregate)| aggregate.add(metric.value))
+                    .or_insert_with(|| (metric.unit.clone(), Aggregate::new(metric.value)));
+            }
+        }
+
+        closed
+    }
+
+    /// Close the open window if `now_secs` is past its end (or the window
+    /// length was reconfigured)
+    pub fn flush(&mut self, now_secs: u64, window_secs: u64) -> Option<Window> {
+        let open = self.open.as_ref()?;
+        if now_secs >= open.start_secs + open.length_secs || open.length_secs != window_secs {
+            self.finish()
+        } else {
+            None
+        }
+    }
+
+    /// Close the open window regardless of time (at shutdown)
+    pub fn finish(&mut self) -> Option<Window> {
+        self.open.take().map(Open::close).filter(|w| !w.series.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    fn reading(temperature: f64) -> Vec<Reading> {
+        vec![Reading::new("node1", 0).with_metric("temperature", temperature, Some("celsius"), MetricKind::Sensor)]
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut aggregator = Aggregator::new();
+
+        assert_eq!(aggregator.add(&reading(20.0), 120, 60), None);
+        assert_eq!(aggregator.add(&reading(23.0), 150, 60), None);
+        let mut glitch = reading(-40.0);
+        glitch[0].metrics[0].quality = Some(Quality::Bad);
+        assert_eq!(aggregator.add(&glitch, 170, 60), None);
+        assert_eq!(aggregator.add(&reading(21.0), 179, 60), None);
+        assert_eq!(aggregator.flush(179, 60), None);
+
+        // First packet of the next window closes [120, 180)
+        let window = aggregator.add(&reading(22.0), 185, 60).unwrap();
+        assert_eq!((window.start_secs, window.length_secs), (120, 60));
+        let series = &window.series[0];
+        assert_eq!((series.node.as_str(), series.metric.as_str()), ("node1", "temperature"));
+        let a = series.aggregate;
+        assert_eq!((a.min, a.max, a.last, a.count), (20.0, 23.0, 21.0, 3));
+        assert!((a.mean() - 64.0 / 3.0).abs() < 1e-9);
+
+        // Timer closes a window nobody else will
+        let window = aggregator.flush(240, 60).unwrap();
+        assert_eq!(window.start_secs, 180);
+        assert_eq!(aggregator.finish(), None);
+    }
+}
@@ -0,0 +1,471 @@
+//! Threshold alerting
+//!
+//! Rules from `[alerts.rules]` are evaluated on every packet in the
+//! processor, so the gateway can alert even when the central Grafana is
+//! unreachable. A rule's condition reads like
+//! - `node1.temperature > 30 for 5m`: value above 30 for five minutes
+//! - `sts.err rate > 5/min`: counter rising faster than 5 per minute
+//! - `rssi < -110`: any node's `rssi` below -110, immediately
+//!
+//! Values are selected as `node.metric`, by firmware JSON path (`sts.err`,
+//! see [`week5::resolve`]) or by metric name alone (every node). Each
+//! rule/node pair moves through ok → pending → firing → resolved → ok;
+//! firing and resolved produce an [`AlertEvent`] for the notifiers. A firing
+//! alert only resolves once the value is `hysteresis` back past the threshold.
+//!
+//! Durations and rates use the firmware timestamps, like validation. Values
+//! flagged bad by validation are ignored.
+
+use crate::config::AlertRule;
+use crate::telemetry::{Quality, Reading};
+use crate::week5;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Comparison in a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+impl Op {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Above => value > threshold,
+            Op::AtLeast => value >= threshold,
+            Op::Below => value < threshold,
+            Op::AtMost => value <= threshold,
+        }
+    }
+
+    /// Threshold moved `hysteresis` towards the clear side
+    fn clear_threshold(self, threshold: f64, hysteresis: f64) -> f64 {
+        match self {
+            Op::Above | Op::AtLeast => threshold - hysteresis,
+            Op::Below | Op::AtMost => threshold + hysteresis,
+        }
+    }
+}
+
+/// Parsed rule condition: `<selector> [rate] <op> <threshold>[/<unit>] [for <duration>]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    /// Node to watch, `None` for every node reporting the metric
+    pub node: Option<String>,
+    pub metric: String,
+    /// Compare the rate of change, in units per `rate_per_secs` seconds
+    pub rate_per_secs: Option<f64>,
+    pub op: Op,
+    pub threshold: f64,
+    /// How long the condition must hold before firing
+    pub for_ms: u64,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected = "expected e.g. \"node1.temperature > 30 for 5m\" or \"sts.err rate > 5/min\"";
+        let mut tokens = s.split_whitespace().peekable();
+
+        let selector = tokens.next().ok_or_else(|| format!("empty condition ({})", expected))?;
+        let (node, metric) = match week5::resolve(selector) {
+            Some((node, metric)) => (Some(node.to_string()), metric.to_string()),
+            None => match selector.split_once('.') {
+                Some((node, metric)) => (Some(node.to_string()), metric.to_string()),
+                None => (None, selector.to_string()),
+            },
+        };
+        if node.as_deref() == Some("") || metric.is_empty() {
+            return Err(format!("invalid selector {:?}", selector));
+        }
+
+        let rate = tokens.next_if_eq(&"rate").is_some();
+        let op = match tokens.next() {
+            Some(">") => Op::Above,
+            Some(">=") => Op::AtLeast,
+            Some("<") => Op::Below,
+            Some("<=") => Op::AtMost,
+            other => return Err(format!("expected >, >=, < or <= but found {:?} ({})", other.unwrap_or(""), expected)),
+        };
+
+        let value = tokens.next().ok_or_else(|| format!("missing threshold ({})", expected))?;
+        let (number, unit) = match value.split_once('/') {
+            Some((number, unit)) => (number, Some(unit)),
+            None => (value, None),
+        };
+        let threshold: f64 = number
+            .parse()
+            .ok()
+            .filter(|t: &f64| t.is_finite())
+            .ok_or_else(|| format!("invalid threshold {:?}", number))?;
+        let rate_per_secs = match (rate, unit) {
+            (false, None) => None,
+            (false, Some(_)) => return Err(format!("per-time threshold {:?} needs \"rate\"", value)),
+            (true, None) => Some(1.0),
+            (true, Some(unit)) => Some(match unit {
+                "s" | "sec" => 1.0,
+                "min" => 60.0,
+                "h" | "hour" => 3600.0,
+                _ => return Err(format!("invalid rate unit {:?} (expected s, min or h)", unit)),
+            }),
+        };
+
+        let for_ms = match tokens.next() {
+            None => 0,
+            Some("for") => {
+                let duration = tokens.next().ok_or_else(|| "missing duration after \"for\"".to_string())?;
+                parse_duration_ms(duration)
+                    .ok_or_else(|| format!("invalid duration {:?} (expected e.g. 30s, 5m, 1h)", duration))?
+            }
+            Some(other) => return Err(format!("unexpected {:?} ({})", other, expected)),
+        };
+        if let Some(extra) = tokens.next() {
+            return Err(format!("unexpected {:?} ({})", extra, expected));
+        }
+
+        Ok(Self {
+            node,
+            metric,
+            rate_per_secs,
+            op,
+            threshold,
+            for_ms,
+        })
+    }
+}
+
+/// Parse `30s`, `5m` or `1h` into milliseconds
+fn parse_duration_ms(s: &str) -> Option<u64> {
+    let (number, unit_ms) = if let Some(number) = s.strip_suffix('s') {
+        (number, 1_000)
+    } else if let Some(number) = s.strip_suffix('m') {
+        (number, 60_000)
+    } else {
+        (s.strip_suffix('h')?, 3_600_000)
+    };
+    let number: u64 = number.parse().ok()?;
+    number.checked_mul(unit_ms)
+}
+
+/// State of one rule for one node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Ok,
+    /// Condition holds, waiting for the `for` duration
+    Pending,
+    Firing,
+    /// Cleared after firing; becomes ok with the next value
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertState::Ok => "ok",
+            AlertState::Pending => "pending",
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// Notification for an alert that started firing or resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub node: String,
+    pub metric: String,
+    pub state: AlertState,
+    pub severity: String,
+    /// Rule condition as configured
+    pub condition: String,
+    /// Value (or rate) that caused the transition
+    pub value: f64,
+    pub threshold: f64,
+    /// Firmware timestamp of the packet
+    pub timestamp_ms: u64,
+}
+
+impl AlertEvent {
+    /// MQTT topic, retained per rule and node: `<prefix>/alerts/<rule>/<node>`
+    pub fn mqtt_topic(&self, prefix: &str) -> String {
+        format!("{}/alerts/{}/{}", prefix, self.rule, self.node)
+    }
+
+    /// JSON payload for MQTT and the webhook
+    pub fn to_json(&self) -> String {
+        json!({
+            "rule": self.rule,
+            "node": self.node,
+            "metric": self.metric,
+            "state": self.state.as_str(),
+            "severity": self.severity,
+            "condition": self.condition,
+            "value": self.value,
+            "threshold": self.threshold,
+            "timestamp_ms": self.timestamp_ms,
+        })
+        .to_string()
+    }
+}
+
+impl fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} on {} ({})", self.rule, self.state.as_str(), self.node, self.condition)
+    }
+}
+
+struct Rule {
+    config: AlertRule,
+    condition: Condition,
+}
+
+/// Per rule and node
+struct Series {
+    state: AlertState,
+    /// When the condition started holding (firmware timestamp)
+    since_ms: u64,
+    /// Previous value and timestamp, for rates
+    previous: Option<(f64, u64)>,
+}
+
+/// Evaluates the configured rules against incoming readings
+#[derive(Default)]
+pub struct AlertEngine {
+    rules: BTreeMap<String, Rule>,
+    series: HashMap<(String, String), Series>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: &BTreeMap<String, AlertRule>) -> Self {
+        let mut engine = Self::default();
+        engine.set_rules(rules);
+        engine
+    }
+
+    /// Replace the rules, keeping the state of rules that didn't change
+    ///
+    /// Rules that don't parse are skipped (config validation reports them).
+    pub fn set_rules(&mut self, rules: &BTreeMap<String, AlertRule>) {
+        let old = std::mem::take(&mut self.rules);
+        for (name, config) in rules {
+            if let Ok(condition) = config.when.parse() {
+                self.rules.insert(
+                    name.clone(),
+                    Rule {
+                        config: config.clone(),
+                        condition,
+                    },
+                );
+            }
+        }
+        self.series.retain(|(rule, _), _| {
+            matches!((old.get(rule), self.rules.get(rule)), (Some(a), Some(b)) if a.config == b.config)
+        });
+    }
+
+    /// Number of rule/node pairs currently firing, by rule
+    pub fn firing(&self) -> BTreeMap<&str, usize> {
+        let mut firing: BTreeMap<&str, usize> = self.rules.keys().map(|name| (name.as_str(), 0)).collect();
+        for ((rule, _), series) in &self.series {
+            if series.state == AlertState::Firing {
+                if let Some(count) = firing.get_mut(rule.as_str()) {
+                    *count += 1;
+                }
+            }
+        }
+        firing
+    }
+
+    /// Evaluate every rule against the readings of one packet
+    pub fn evaluate(&mut self, readings: &[Reading]) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+
+        for (name, rule) in &self.rules {
+            let condition = &rule.condition;
+            for reading in readings {
+                if condition.node.as_ref().is_some_and(|node| *node != reading.node_id) {
+                    continue;
+                }
+                // Implausible values shouldn't page anyone
+                let Some(metric) = reading
+                    .metric(&condition.metric)
+                    .filter(|m| m.quality != Some(Quality::Bad))
+                else {
+                    continue;
+                };
+
+                let ts = reading.timestamp_ms;
+                let series = self
+                    .series
+                    .entry((name.clone(), reading.node_id.clone()))
+                    .or_insert(Series {
+                        state: AlertState::Ok,
+                        since_ms: ts,
+                        previous: None,
+                    });
+
+                let value = match condition.rate_per_secs {
+                    None => metric.value,
+                    Some(per_secs) => {
+                        let previous = series.previous.replace((metric.value, ts));
+                        match previous {
+                            // Counter reset or timestamp jump (reboot): start over
+                            Some((prev, prev_ts)) if ts > prev_ts && metric.value >= prev => {
+                                (metric.value - prev) / ((ts - prev_ts) as f64 / 1000.0) * per_secs
+                            }
+                            _ => continue,
+                        }
+                    }
+                };
+
+                if let Some(state) = step(series, condition, rule.config.hysteresis, value, ts) {
+                    events.push(AlertEvent {
+                        rule: name.clone(),
+                        node: reading.node_id.clone(),
+                        metric: condition.metric.clone(),
+                        state,
+                        severity: rule.config.severity.clone(),
+                        condition: rule.config.when.clone(),
+                        value,
+                        threshold: condition.threshold,
+                        timestamp_ms: ts,
+                    });
+                }
+            }
+        }
+
+        events
+    }
+}
+
+/// Advance one series, returning the new state if it should be notified
+fn step(series: &mut Series, condition: &Condition, hysteresis: f64, value: f64, ts: u64) -> Option<AlertState> {
+    let threshold = match series.state {
+        AlertState::Firing => condition.op.clear_threshold(condition.threshold, hysteresis),
+        _ => condition.threshold,
+    };
+    let active = condition.op.holds(value, threshold);
+
+    let next = match (series.state, active) {
+        (AlertState::Firing, true) => AlertState::Firing,
+        (AlertState::Firing, false) => AlertState::Resolved,
+        (AlertState::Pending, true) => {
+            // Timestamps went backwards (node rebooted): restart the wait
+            if ts < series.since_ms {
+                series.since_ms = ts;
+            }
+            if ts - series.since_ms >= condition.for_ms {
+                AlertState::Firing
+            } else {
+                AlertState::Pending
+            }
+        }
+        (_, true) => {
+            series.since_ms = ts;
+            if condition.for_ms == 0 {
+                AlertState::Firing
+            } else {
+                AlertState::Pending
+            }
+        }
+        (_, false) => AlertState::Ok,
+    };
+
+    let notify = next != series.state && matches!(next, AlertState::Firing | AlertState::Resolved);
+    series.state = next;
+    notify.then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    fn rule(when: &str, hysteresis: f64) -> AlertRule {
+        AlertRule {
+            when: when.to_string(),
+            hysteresis,
+            severity: "warning".to_string(),
+        }
+    }
+
+    fn reading(node: &str, ts: u64, metric: &str, value: f64) -> Vec<Reading> {
+        vec![Reading::new(node, ts).with_metric(metric, value, None, MetricKind::Sensor)]
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        let c: Condition = "node1.temperature > 30 for 5m".parse().unwrap();
+        assert_eq!(c.node.as_deref(), Some("node1"));
+        assert_eq!((c.metric.as_str(), c.op, c.threshold, c.for_ms), ("temperature", Op::Above, 30.0, 300_000));
+
+        // Firmware JSON path, per-minute rate
+        let c: Condition = "sts.err rate > 5/min".parse().unwrap();
+        assert_eq!((c.node.as_deref(), c.metric.as_str()), (Some("stats"), "crc_errors"));
+        assert_eq!(c.rate_per_secs, Some(60.0));
+
+        let c: Condition = "rssi < -110".parse().unwrap();
+        assert_eq!((c.node, c.op, c.for_ms), (None, Op::Below, 0));
+
+        let invalid = [
+            "",
+            "rssi",
+            "rssi = 3",
+            "rssi < x",
+            "rssi < 3/min",
+            "rssi < 3 for",
+            "rssi < 3 for 5x",
+            "rssi < 3 for 5µ",
+            "rssi < 3 for 99999999999999999h",
+            "rssi < 3 now",
+        ];
+        for bad in invalid {
+            assert!(bad.parse::<Condition>().is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn test_alert_lifecycle() {
+        let rules = BTreeMap::from([("hot".to_string(), rule("node1.temperature > 30 for 1m", 1.0))]);
+        let mut engine = AlertEngine::new(&rules);
+        let mut states = |ts, value| {
+            let events = engine.evaluate(&reading("node1", ts, "temperature", value));
+            events.first().map(|e| e.state)
+        };
+
+        assert_eq!(states(0, 31.0), None); // pending
+        assert_eq!(states(30_000, 29.0), None); // back to ok before firing
+        assert_eq!(states(40_000, 31.0), None);
+        assert_eq!(states(100_000, 32.0), Some(AlertState::Firing));
+        // Within the hysteresis band: still firing
+        assert_eq!(states(110_000, 29.5), None);
+        assert_eq!(states(120_000, 28.9), Some(AlertState::Resolved));
+        assert_eq!(states(130_000, 28.0), None);
+        assert_eq!(engine.firing()["hot"], 0);
+    }
+
+    #[test]
+    fn test_rate_alert() {
+        let rules = BTreeMap::from([("crc".to_string(), rule("sts.err rate > 5/min", 0.0))]);
+        let mut engine = AlertEngine::new(&rules);
+
+        assert!(engine.evaluate(&reading("stats", 0, "crc_errors", 10.0)).is_empty());
+        // 3 errors in 60 s
+        assert!(engine.evaluate(&reading("stats", 60_000, "crc_errors", 13.0)).is_empty());
+        // 10 errors in 60 s
+        let events = engine.evaluate(&reading("stats", 120_000, "crc_errors", 23.0));
+        assert_eq!(events[0].state, AlertState::Firing);
+        assert_eq!(events[0].value, 10.0);
+        assert_eq!(events[0].mqtt_topic("iiot"), "iiot/alerts/crc/stats");
+        // Counter reset after a reboot is skipped, not a negative rate
+        assert!(engine.evaluate(&reading("stats", 1_000, "crc_errors", 0.0)).is_empty());
+        assert_eq!(engine.firing()["crc"], 1);
+    }
+}
@@ -0,0 +1,168 @@
+//! Node availability (last-seen tracking)
+//!
+//! A node that stops transmitting just goes quiet, leaving its retained MQTT
+//! values frozen. The processor records when each node was last heard from
+//! and checks periodically; a node with no packet within its timeout goes
+//! offline, and the next packet brings it back online. Each transition is
+//! reported once so sinks can publish it.
+//!
+//! Timing uses the gateway clock: there are no firmware timestamps while a
+//! node is silent.
+//!
+//! Only readings with at least one sensor value count as a node. The
+//! link-only and counter-only readings week5 decodes (`signal`, `stats`)
+//! describe the gateway's radio, not a node that can go quiet.
+
+use crate::telemetry::{MetricKind, Reading};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// A node going online or offline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+    pub node: String,
+    pub online: bool,
+    /// Retained metric topics of the node, to mark stale / fresh
+    pub retained: Vec<String>,
+}
+
+struct NodeStatus {
+    last_seen: Instant,
+    online: bool,
+    retained: BTreeSet<String>,
+}
+
+/// Last-seen state of every node
+#[derive(Default)]
+pub struct Availability {
+    nodes: HashMap<String, NodeStatus>,
+}
+
+impl Availability {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a packet's readings; returns nodes that are new or back online
+    pub fn seen(&mut self, readings: &[Reading], now: Instant) -> Vec<StatusChange> {
+        let mut changes = Vec::new();
+
+        for reading in readings.iter().filter(|r| is_node(r)) {
+            let retained = reading
+                .metrics
+                .iter()
+                .filter(|m| m.kind.retained())
+                .map(|m| m.name.clone());
+
+            match self.nodes.get_mut(&reading.node_id) {
+                Some(status) => {
+                    status.last_seen = now;
+                    status.retained.extend(retained);
+                    if !status.online {
+                        status.online = true;
+                        changes.push(change(&reading.node_id, status));
+                    }
+                }
+                None => {
+                    let status = NodeStatus {
+                        last_seen: now,
+                        online: true,
+                        retained: retained.collect(),
+                    };
+                    changes.push(change(&reading.node_id, &status));
+                    self.nodes.insert(reading.node_id.clone(), status);
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Mark nodes offline that weren't seen within `timeout(node)`
+    pub fn check(&mut self, now: Instant, timeout: impl Fn(&str) -> Duration) -> Vec<StatusChange> {
+        let mut changes = Vec::new();
+
+        for (node, status) in self.nodes.iter_mut() {
+            if status.online && now.duration_since(status.last_seen) > timeout(node) {
+                status.online = false;
+                changes.push(change(node, status));
+            }
+        }
+
+        changes.sort_by(|a, b| a.node.cmp(&b.node));
+        changes
+    }
+
+    /// Time since each node was last seen
+    pub fn last_seen(&self, now: Instant) -> impl Iterator<Item = (&str, Duration)> {
+        self.nodes
+            .iter()
+            .map(move |(node, status)| (node.as_str(), now.duration_since(status.last_seen)))
+    }
+}
+
+/// Whether a reading comes from a radio node rather than the link or counters
+fn is_node(reading: &Reading) -> bool {
+    reading.metrics.iter().any(|m| m.kind == MetricKind::Sensor)
+}
+
+fn change(node: &str, status: &NodeStatus) -> StatusChange {
+    StatusChange {
+        node: node.to_string(),
+        online: status.online,
+        retained: status.retained.iter().cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    #[test]
+    fn test_offline_and_back() {
+        let mut availability = Availability::new();
+        let start = Instant::now();
+        let timeout = |node: &str| Duration::from_secs(if node == "node2" { 120 } else { 60 });
+        let readings = vec![
+            Reading::new("node1", 0)
+                .with_metric("temperature", 21.0, Some("celsius"), MetricKind::Sensor)
+                .with_metric("rssi", -70.0, Some("dbm"), MetricKind::Link),
+            Reading::new("node2", 0).with_metric("humidity", 40.0, Some("percent"), MetricKind::Sensor),
+        ];
+
+        let changes = availability.seen(&readings, start);
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].online);
+        // Only retained (sensor) values get marked stale
+        assert_eq!(changes[0].retained, vec!["temperature"]);
+
+        // Still within the timeouts, and no change on a repeat packet
+        assert!(availability.seen(&readings[1..], start + Duration::from_secs(30)).is_empty());
+        assert!(availability.check(start + Duration::from_secs(60), timeout).is_empty());
+
+        let changes = availability.check(start + Duration::from_secs(61), timeout);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].node.as_str(), changes[0].online), ("node1", false));
+        // Reported once
+        assert!(availability.check(start + Duration::from_secs(90), timeout).is_empty());
+
+        let changes = availability.seen(&readings[..1], start + Duration::from_secs(100));
+        assert_eq!((changes[0].node.as_str(), changes[0].online), ("node1", true));
+    }
+
+    #[test]
+    fn test_link_and_counters_are_not_nodes() {
+        let mut availability = Availability::new();
+        let readings = vec![
+            Reading::new("node1", 0).with_metric("temperature", 21.0, Some("celsius"), MetricKind::Sensor),
+            Reading::new("signal", 0).with_metric("rssi", -70.0, Some("dbm"), MetricKind::Link),
+            Reading::new("stats", 0).with_metric("packets_received", 10.0, None, MetricKind::Counter),
+        ];
+
+        let changes = availability.seen(&readings, Instant::now());
+        let nodes: Vec<_> = changes.iter().map(|c| c.node.as_str()).collect();
+        assert_eq!(nodes, vec!["node1"]);
+        assert_eq!(availability.last_seen(Instant::now()).count(), 1);
+    }
+}
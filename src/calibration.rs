@@ -0,0 +1,85 @@
+//! Per-node sensor calibration
+//!
+//! Corrections from `[calibration]` are applied right after decoding, so
+//! validation, derived metrics and every sink see calibrated values. Each
+//! entry is keyed by `node/metric` and is either linear
+//! (`value * gain + offset`) or a polynomial in the raw value.
+
+use crate::config::Calibration;
+use crate::telemetry::Reading;
+use std::collections::BTreeMap;
+
+/// Corrected value for a raw reading
+pub fn apply(calibration: &Calibration, raw: f64) -> f64 {
+    match &calibration.polynomial {
+        // Horner's scheme, coefficients from the constant term up
+        Some(coefficients) => coefficients.iter().rev().fold(0.0, |acc, c| acc * raw + c),
+        None => raw * calibration.gain.unwrap_or(1.0) + calibration.offset.unwrap_or(0.0),
+    }
+}
+
+/// Apply the calibration table to every matching metric
+pub fn calibrate(readings: &mut [Reading], table: &BTreeMap<String, Calibration>) {
+    if table.is_empty() {
+        return;
+    }
+
+    for reading in readings.iter_mut() {
+        for metric in reading.metrics.iter_mut() {
+            if let Some(calibration) = table.get(&format!("{}/{}", reading.node_id, metric.name)) {
+                metric.value = apply(calibration, metric.value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    #[test]
+    fn test_calibrate() {
+        let table = BTreeMap::from([
+            (
+                "node2/temperature".to_string(),
+                Calibration {
+                    offset: Some(-1.2),
+                    ..Default::default()
+                },
+            ),
+            (
+                "node2/humidity".to_string(),
+                Calibration {
+                    gain: Some(1.1),
+                    offset: Some(-2.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                "node1/gas_resistance".to_string(),
+                Calibration {
+                    polynomial: Some(vec![10.0, 2.0, 0.5]),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let mut readings = vec![
+            Reading::new("node1", 0)
+                .with_metric("temperature", 25.0, Some("celsius"), MetricKind::Sensor)
+                .with_metric("gas_resistance", 4.0, Some("ohms"), MetricKind::Sensor),
+            Reading::new("node2", 0)
+                .with_metric("temperature", 25.0, Some("celsius"), MetricKind::Sensor)
+                .with_metric("humidity", 50.0, Some("percent"), MetricKind::Sensor),
+        ];
+        calibrate(&mut readings, &table);
+
+        // Other nodes' metrics of the same name are untouched
+        assert_eq!(readings[0].metric("temperature").unwrap().value, 25.0);
+        // 10 + 2*4 + 0.5*16
+        assert_eq!(readings[0].metric("gas_resistance").unwrap().value, 26.0);
+        assert!((readings[1].metric("temperature").unwrap().value - 23.8).abs() < 1e-9);
+        assert!((readings[1].metric("humidity").unwrap().value - 53.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,153 @@
+//! Command-line interface for the gateway binary
+//!
+//! Subcommands:
+//! - `run` (default): flash and run the firmware via probe-rs, forward telemetry
+//! - `check-config`: load and validate the configuration, then exit
+//! - `replay <file>`: feed a captured probe-rs log (or NDJSON) through the pipeline
+//! - `simulate`: feed synthetic telemetry through the pipeline (no hardware)
+//! - `test-mqtt`: publish a test message to the broker
+//! - `test-influx`: health check and test write against InfluxDB
+
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use wk7_mqtt_influx::config::{Config, LogFormat};
+
+/// Week 7 MQTT + InfluxDB Gateway Service
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the configuration file
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    pub config: PathBuf,
+
+    /// Log filter (e.g. "info", "debug", "wk7_mqtt_influx=trace"); overrides RUST_LOG
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// Log output format ("pretty" or "json"); overrides logging.format
+    #[arg(long, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the gateway against the hardware via probe-rs (default)
+    Run,
+    /// Validate the configuration file and exit
+    CheckConfig,
+    /// Replay a captured probe-rs log or NDJSON file through the pipeline
+    Replay {
+        /// File with one probe-rs log line or JSON packet per line
+        file: PathBuf,
+        /// Delay between packets in milliseconds (0 = as fast as possible)
+        #[arg(long, default_value_t = 0)]
+        interval_ms: u64,
+    },
+    /// Generate synthetic telemetry and run it through the pipeline
+    Simulate {
+        /// Delay between packets in milliseconds
+        #[arg(long, default_value_t = 2000)]
+        interval_ms: u64,
+        /// Stop after this many packets (default: run until Ctrl+C)
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Publish a test message to the MQTT broker and exit
+    TestMqtt,
+    /// Check InfluxDB health, write a test point and exit
+    TestInflux,
+}
+
+/// Per-field configuration overrides, applied on top of config.toml
+#[derive(Debug, Default, Clone, Args)]
+pub struct ConfigOverrides {
+    /// Override mqtt.broker_url
+    #[arg(long, global = true)]
+    pub broker_url: Option<String>,
+    /// Override mqtt.client_id
+    #[arg(long, global = true)]
+    pub client_id: Option<String>,
+    /// Override mqtt.topic_prefix
+    #[arg(long, global = true)]
+    pub topic_prefix: Option<String>,
+    /// Override mqtt.qos
+    #[arg(long, global = true)]
+    pub qos: Option<u8>,
+    /// Override influxdb.url
+    #[arg(long, global = true)]
+    pub influx_url: Option<String>,
+    /// Override influxdb.org
+    #[arg(long, global = true)]
+    pub influx_org: Option<String>,
+    /// Override influxdb.bucket
+    #[arg(long, global = true)]
+    pub influx_bucket: Option<String>,
+    /// Override gateway.probe_id
+    #[arg(long, global = true)]
+    pub probe_id: Option<String>,
+    /// Override gateway.chip
+    #[arg(long, global = true)]
+    pub chip: Option<String>,
+    /// Override gateway.firmware_path
+    #[arg(long, global = true)]
+    pub firmware_path: Option<String>,
+    /// Override gateway.channel_capacity
+    #[arg(long, global = true)]
+    pub channel_capacity: Option<usize>,
+}
+
+impl ConfigOverrides {
+    /// Apply every override that was given on the command line
+    pub fn apply(&self, config: &mut Config) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(v) = value {
+                *target = v.clone();
+            }
+        }
+
+        set(&mut config.mqtt.broker_url, &self.broker_url);
+        set(&mut config.mqtt.client_id, &self.client_id);
+        set(&mut config.mqtt.topic_prefix, &self.topic_prefix);
+        set(&mut config.mqtt.qos, &self.qos);
+        set(&mut config.influxdb.url, &self.influx_url);
+        set(&mut config.influxdb.org, &self.influx_org);
+        set(&mut config.influxdb.bucket, &self.influx_bucket);
+        set(&mut config.gateway.probe_id, &self.probe_id);
+        set(&mut config.gateway.chip, &self.chip);
+        set(&mut config.gateway.firmware_path, &self.firmware_path);
+        set(&mut config.gateway.channel_capacity, &self.channel_capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_parsing() {
+        let cli = Cli::try_parse_from(["gw", "--config", "/etc/wk7/config.toml", "check-config"]).unwrap();
+        assert_eq!(cli.config, PathBuf::from("/etc/wk7/config.toml"));
+        assert!(matches!(cli.command, Some(Command::CheckConfig)));
+
+        // No subcommand defaults to run (handled in main)
+        let cli = Cli::try_parse_from(["gw"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["gw", "replay", "capture.log", "--qos", "2", "--log-format", "json"]).unwrap();
+        assert_eq!(cli.overrides.qos, Some(2));
+        assert_eq!(cli.log_format, Some(LogFormat::Json));
+        match cli.command {
+            Some(Command::Replay { file, interval_ms }) => {
+                assert_eq!(file, PathBuf::from("capture.log"));
+                assert_eq!(interval_ms, 0);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+}
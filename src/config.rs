@@ -1,35 +1,291 @@
 //! Configuration management for Week 7 Gateway
 //!
 //! Loads configuration from config.toml with environment variable overrides
+//!
+//! Any field can be overridden with `WK7__<SECTION>__<FIELD>`, e.g.
+//! `WK7__MQTT__BROKER_URL` or `WK7__INFLUXDB__DOWNSAMPLING__BUCKET`.
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 
+/// Prefix for environment variable overrides
+pub const ENV_PREFIX: &str = "WK7__";
+
+/// Separator between path segments in override variable names
+const ENV_SEPARATOR: &str = "__";
+
 /// Complete gateway configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub mqtt: MqttConfig,
     pub influxdb: InfluxDbConfig,
     pub gateway: GatewayConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub derived: DerivedConfig,
+    /// Sensor corrections keyed by `node/metric`
+    #[serde(default)]
+    pub calibration: BTreeMap<String, Calibration>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub availability: AvailabilityConfig,
+    #[serde(default)]
+    pub link: LinkConfig,
+    #[serde(default)]
+    pub aggregation: AggregationConfig,
+    #[serde(default)]
+    pub mqtt_deadband: DeadbandConfig,
+    /// Sinks processed telemetry is written to (`[[sinks]]`)
+    #[serde(default = "default_sinks")]
+    pub sinks: Vec<SinkConfig>,
+    /// Per-sink queues keyed by sink name
+    #[serde(default)]
+    pub queues: BTreeMap<String, QueueConfig>,
 }
 
+/// Placeholder printed instead of secrets
+const REDACTED: &str = "<redacted>";
+
 /// MQTT broker configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MqttConfig {
     pub broker_url: String,
     pub client_id: String,
     pub topic_prefix: String,
     pub qos: u8,
+    /// Broker username (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Broker password (optional, prefer password_file)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// File to read the broker password from, e.g. a Docker secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+}
+
+impl std::fmt::Debug for MqttConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MqttConfig")
+            .field("broker_url", &self.broker_url)
+            .field("client_id", &self.client_id)
+            .field("topic_prefix", &self.topic_prefix)
+            .field("qos", &self.qos)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("password_file", &self.password_file)
+            .finish()
+    }
 }
 
 /// InfluxDB configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct InfluxDbConfig {
     pub url: String,
     pub org: String,
     pub bucket: String,
+    /// API token (may be left empty when token_file is set)
+    #[serde(default)]
     pub token: String,
+    /// File to read the API token from, e.g. a Docker secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<String>,
+    /// Long-term rollups of the raw telemetry (optional)
+    #[serde(default)]
+    pub downsampling: Option<DownsamplingConfig>,
+    /// Write buffered points once this many are queued
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Write buffered points at least this often (0 = write every packet)
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+}
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+impl std::fmt::Debug for InfluxDbConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InfluxDbConfig")
+            .field("url", &self.url)
+            .field("org", &self.org)
+            .field("bucket", &self.bucket)
+            .field("token", &REDACTED)
+            .field("token_file", &self.token_file)
+            .field("downsampling", &self.downsampling)
+            .field("batch_size", &self.batch_size)
+            .field("flush_interval_ms", &self.flush_interval_ms)
+            .finish()
+    }
+}
+
+/// Downsampling configuration for long-term storage
+///
+/// Installs one InfluxDB task per window that writes mean/min/max rollups
+/// of the raw bucket into a separate long-retention bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownsamplingConfig {
+    /// Install the rollup tasks at startup
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Destination bucket for rollups (created if missing)
+    pub bucket: String,
+    /// Retention of the rollup bucket in days (0 = keep forever)
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    /// Rollup windows as Flux durations (e.g. "1m", "1h")
+    #[serde(default = "default_downsampling_windows")]
+    pub windows: Vec<String>,
+    /// Prefix for the task names, one task per window
+    #[serde(default = "default_task_prefix")]
+    pub task_prefix: String,
+}
+
+pub fn default_true() -> bool {
+    true
+}
+
+fn default_retention_days() -> u32 {
+    365
+}
+
+fn default_downsampling_windows() -> Vec<String> {
+    vec!["1m".to_string(), "1h".to_string()]
+}
+
+fn default_task_prefix() -> String {
+    "wk7-downsample".to_string()
+}
+
+/// Read a secret from a file, trimming surrounding whitespace
+fn read_secret_file(path: &str) -> Result<String> {
+    let secret = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read secret file: {}", path))?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        anyhow::bail!("Secret file is empty: {}", path);
+    }
+    Ok(secret.to_string())
+}
+
+/// Whether a config path holds a secret that must not be logged
+pub fn is_secret_key(path: &str) -> bool {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    ["token", "password", "secret"].iter().any(|s| key.contains(s))
+}
+
+/// Look up a dotted path in a TOML value
+fn lookup_toml_path<'a>(root: &'a toml::Value, segments: &[String]) -> Option<&'a toml::Value> {
+    segments
+        .iter()
+        .try_fold(root, |value, key| value.as_table()?.get(key))
+}
+
+/// Get the table at a dotted path, creating missing sections
+fn toml_table_mut<'a>(root: &'a mut toml::Value, segments: &[String]) -> Result<&'a mut toml::Table> {
+    let mut table = root.as_table_mut().context("configuration is not a table")?;
+    for key in segments {
+        table = table
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("{} is not a section", key))?;
+    }
+    Ok(table)
+}
+
+/// Set a dotted path in a TOML value, parsing `raw` to match the existing type
+///
+/// `base` is the configuration before any overrides, used to find out what
+/// an unset optional field accepts.
+fn set_toml_path(root: &mut toml::Value, base: &toml::Value, segments: &[String], raw: &str) -> Result<()> {
+    let (last, parents) = segments.split_last().context("empty path")?;
+    let table = toml_table_mut(root, parents)?;
+
+    let value = match table.get(last) {
+        Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+        Some(toml::Value::Integer(_)) => toml::Value::Integer(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected an integer, got {:?}", raw))?,
+        ),
+        Some(toml::Value::Float(_)) => toml::Value::Float(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected a number, got {:?}", raw))?,
+        ),
+        Some(toml::Value::Boolean(_)) => toml::Value::Boolean(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("expected true or false, got {:?}", raw))?,
+        ),
+        Some(toml::Value::Table(_)) => {
+            anyhow::bail!("is a section, set its fields individually")
+        }
+        Some(_) => parse_toml_literal(raw)
+            .with_context(|| format!("expected a TOML literal, got {:?}", raw))?,
+        None => unset_field_value(base, parents, last, raw),
+    };
+
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Value for an unset optional field, which has no type to go by
+///
+/// Takes `raw` as a TOML literal if the field accepts that, and as a string
+/// otherwise, so a numeric password stays a string while an unset number or
+/// list is still parsed.
+fn unset_field_value(base: &toml::Value, parents: &[String], last: &str, raw: &str) -> toml::Value {
+    let string = toml::Value::String(raw.to_string());
+    let literal = match parse_toml_literal(raw) {
+        Ok(literal) if !literal.is_str() => literal,
+        _ => return string,
+    };
+
+    let accepts = |value: &toml::Value| {
+        let mut trial = base.clone();
+        toml_table_mut(&mut trial, parents)
+            .map(|table| table.insert(last.to_string(), value.clone()))
+            .is_ok()
+            && trial.try_into::<Config>().is_ok()
+    };
+    if !accepts(&literal) && accepts(&string) {
+        string
+    } else {
+        literal
+    }
+}
+
+/// Parse a single TOML value such as `["1m", "1h"]` or `42`
+fn parse_toml_literal(raw: &str) -> Result<toml::Value> {
+    let mut doc: toml::Table = toml::from_str(&format!("v = {}", raw))?;
+    doc.remove("v").context("missing value")
+}
+
+/// Check that a string is a simple Flux duration like "30s", "1m", "1h" or "7d"
+pub fn is_flux_duration(s: &str) -> bool {
+    let Some(unit) = s.chars().last() else {
+        return false;
+    };
+    let num = &s[..s.len() - unit.len_utf8()];
+    !num.is_empty()
+        && num.chars().all(|c| c.is_ascii_digit())
+        && num.parse::<u64>().map(|n| n > 0).unwrap_or(false)
+        && matches!(unit, 's' | 'm' | 'h' | 'd')
 }
 
 /// Gateway hardware configuration
@@ -39,15 +295,526 @@ pub struct GatewayConfig {
     pub chip: String,
     pub firmware_path: String,
     pub channel_capacity: usize,
+    /// Restart probe-rs when it exits instead of shutting down
+    #[serde(default)]
+    pub restart_on_exit: bool,
+    /// Delay before restarting probe-rs
+    #[serde(default = "default_restart_delay_secs")]
+    pub restart_delay_secs: u64,
+}
+
+fn default_restart_delay_secs() -> u64 {
+    5
+}
+
+/// Embedded HTTP server configuration (/metrics, /healthz, /readyz)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Serve the HTTP endpoints
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Address to listen on
+    #[serde(default = "default_http_listen_addr")]
+    pub listen_addr: String,
+    /// /readyz fails if no packet arrived for this long
+    #[serde(default = "default_ready_timeout_secs")]
+    pub ready_timeout_secs: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen_addr: default_http_listen_addr(),
+            ready_timeout_secs: default_ready_timeout_secs(),
+        }
+    }
+}
+
+fn default_ready_timeout_secs() -> u64 {
+    60
+}
+
+fn default_http_listen_addr() -> String {
+    "0.0.0.0:9108".to_string()
+}
+
+/// Plausibility checks applied before publishing
+///
+/// Rules are keyed by metric name (`temperature`) or, to override it for
+/// one node, by `node/metric` (`"node2/temperature"`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    /// Run the validation stage
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Publish values flagged bad to MQTT (InfluxDB always gets them, tagged)
+    #[serde(default)]
+    pub mqtt_publish_bad: bool,
+    /// Per-metric rules (replaces the built-in rules when given)
+    #[serde(default = "default_validation_rules")]
+    pub rules: BTreeMap<String, MetricRule>,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mqtt_publish_bad: false,
+            rules: default_validation_rules(),
+        }
+    }
+}
+
+/// Plausibility rule for one metric
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricRule {
+    /// Values below this are bad
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Values above this are bad
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Changing faster than this (units per second) is suspect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rate_per_sec: Option<f64>,
+    /// This many identical values in a row are suspect (stuck sensor)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stuck_count: Option<u32>,
+}
+
+/// Built-in rules for the Week 5 sensors (BME680, SHT3x)
+fn default_validation_rules() -> BTreeMap<String, MetricRule> {
+    let rule = |min, max, max_rate_per_sec, stuck_count| MetricRule {
+        min,
+        max,
+        max_rate_per_sec,
+        stuck_count,
+    };
+    BTreeMap::from([
+        ("temperature".to_string(), rule(Some(-30.0), Some(70.0), Some(1.0), None)),
+        ("humidity".to_string(), rule(Some(0.0), Some(100.0), Some(5.0), None)),
+        ("gas_resistance".to_string(), rule(Some(1.0), None, None, Some(30))),
+    ])
+}
+
+/// Correction for one node's metric
+///
+/// Linear (`value * gain + offset`) or, if `polynomial` is given,
+/// `c0 + c1*value + c2*value^2 + ...` with the coefficients in order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Added after scaling (default 0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// Scale factor (default 1)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain: Option<f64>,
+    /// Polynomial coefficients, constant term first (replaces offset/gain)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polynomial: Option<Vec<f64>>,
+}
+
+/// Derived metrics (dew point, absolute humidity, heat index, IAQ)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedConfig {
+    /// Compute derived metrics
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Clean-air gas resistance for the IAQ estimate (default: highest seen)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iaq_gas_baseline_ohms: Option<f64>,
+}
+
+impl Default for DerivedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            iaq_gas_baseline_ohms: None,
+        }
+    }
+}
+
+/// Windowed aggregation before writing to InfluxDB (see [`crate::aggregation`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregationConfig {
+    /// Write per-window aggregates
+    #[serde(default)]
+    pub enabled: bool,
+    /// Window length
+    #[serde(default = "default_aggregation_window_secs")]
+    pub window_secs: u64,
+    /// Keep writing raw points alongside the aggregates
+    #[serde(default = "default_true")]
+    pub write_raw: bool,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_secs: default_aggregation_window_secs(),
+            write_raw: true,
+        }
+    }
+}
+
+fn default_aggregation_window_secs() -> u64 {
+    60
+}
+
+/// One entry of the `[[sinks]]` list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SinkConfig {
+    /// Sink type: "mqtt", "influxdb" or "file"
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Name for logs, metrics and `[queues.<name>]` (default: the type)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Type-specific settings
+    #[serde(flatten)]
+    pub options: toml::Table,
+}
+
+impl SinkConfig {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            name: None,
+            enabled: true,
+            options: toml::Table::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.kind)
+    }
+}
+
+/// MQTT and InfluxDB, as before sinks were configurable
+fn default_sinks() -> Vec<SinkConfig> {
+    vec![SinkConfig::new("mqtt"), SinkConfig::new("influxdb")]
+}
+
+/// Sink queue between the processor and a sink's worker (see [`crate::fanout`])
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueConfig {
+    /// Events the queue holds before the overflow policy applies
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_queue_capacity(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+fn default_queue_capacity() -> usize {
+    1000
+}
+
+/// What to do when a sink's queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for room (lossless, but stalls the processor and every other sink)
+    Block,
+    /// Discard the oldest queued event
+    #[default]
+    DropOldest,
+    /// Discard the new event
+    DropNewest,
+}
+
+/// MQTT report-by-exception (see [`crate::deadband`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadbandConfig {
+    /// Only publish values that changed meaningfully
+    #[serde(default)]
+    pub enabled: bool,
+    /// Republish unchanged values after this long (heartbeat)
+    #[serde(default = "default_max_silence_secs")]
+    pub max_silence_secs: u64,
+    /// Deadbands keyed by `metric` or `node/metric`
+    #[serde(default)]
+    pub rules: BTreeMap<String, DeadbandRule>,
+}
+
+impl Default for DeadbandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_silence_secs: default_max_silence_secs(),
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_max_silence_secs() -> u64 {
+    300
+}
+
+/// Smallest change worth publishing; either threshold is enough
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeadbandRule {
+    /// Change in the metric's unit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub absolute: Option<f64>,
+    /// Change relative to the last published value, in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+}
+
+/// LoRa link-quality analytics (see [`crate::link`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkConfig {
+    /// Add link analytics metrics
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Packets the rolling statistics cover
+    #[serde(default = "default_link_window")]
+    pub window: usize,
+    /// LoRa spreading factor the link runs at (7-12)
+    #[serde(default = "default_spreading_factor")]
+    pub spreading_factor: u8,
+    /// LoRa bandwidth in kHz
+    #[serde(default = "default_bandwidth_khz")]
+    pub bandwidth_khz: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window: default_link_window(),
+            spreading_factor: default_spreading_factor(),
+            bandwidth_khz: default_bandwidth_khz(),
+        }
+    }
+}
+
+/// About ten minutes at one packet per ~10 s
+fn default_link_window() -> usize {
+    60
+}
+
+/// Firmware setting: `AT+PARAMETER=7,9,1,7` (SF7, 500 kHz)
+fn default_spreading_factor() -> u8 {
+    7
+}
+
+fn default_bandwidth_khz() -> f64 {
+    500.0
+}
+
+/// Threshold alerting (see [`crate::alerts`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertsConfig {
+    /// Evaluate alert rules
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// POST notifications as JSON to this URL (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// Timeout for one webhook request
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub webhook_timeout_secs: u64,
+    /// Rules by name; the name is used in the MQTT topic
+    #[serde(default)]
+    pub rules: BTreeMap<String, AlertRule>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            webhook_url: None,
+            webhook_timeout_secs: default_webhook_timeout_secs(),
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    5
+}
+
+/// One alert rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Condition, e.g. "node1.temperature > 30 for 5m"
+    pub when: String,
+    /// How far back past the threshold the value must go to resolve
+    #[serde(default)]
+    pub hysteresis: f64,
+    /// Free-form severity passed on in notifications
+    #[serde(default = "default_alert_severity")]
+    pub severity: String,
+}
+
+fn default_alert_severity() -> String {
+    "warning".to_string()
+}
+
+/// Node offline detection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailabilityConfig {
+    /// Track node availability
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// A node is offline after this long without a packet
+    #[serde(default = "default_offline_timeout_secs")]
+    pub offline_timeout_secs: u64,
+    /// Per-node timeouts overriding `offline_timeout_secs`
+    #[serde(default)]
+    pub node_timeouts: BTreeMap<String, u64>,
+}
+
+impl AvailabilityConfig {
+    /// Offline timeout for `node`
+    pub fn timeout(&self, node: &str) -> std::time::Duration {
+        let secs = self.node_timeouts.get(node).copied().unwrap_or(self.offline_timeout_secs);
+        std::time::Duration::from_secs(secs)
+    }
+}
+
+impl Default for AvailabilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            offline_timeout_secs: default_offline_timeout_secs(),
+            node_timeouts: BTreeMap::new(),
+        }
+    }
+}
+
+/// Six missed packets at the Week 5 rate of one per ~10 s
+fn default_offline_timeout_secs() -> u64 {
+    60
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Log filter, e.g. "info" or "info,wk7_mqtt_influx=debug"
+    /// (RUST_LOG and --log-level take precedence)
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// Output format on stdout and in the log file (--log-format takes precedence)
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Optional log file, written in addition to stdout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<LogFileConfig>,
+    /// Publish firmware log lines at or above this level (e.g. "warn") to
+    /// `<topic_prefix>/gateway/log`; "off" disables
+    #[serde(default = "default_firmware_mqtt_level")]
+    pub firmware_mqtt_level: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+            file: None,
+            firmware_mqtt_level: default_firmware_mqtt_level(),
+        }
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_firmware_mqtt_level() -> String {
+    "off".to_string()
+}
+
+/// Log output format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per event, with fields at the top level
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format {:?} (expected \"pretty\" or \"json\")", other)),
+        }
+    }
+}
+
+/// Rotating log file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogFileConfig {
+    /// File path; rotated files get a date suffix (e.g. gateway.log.2024-06-01)
+    pub path: String,
+    /// How often to start a new file
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// Rotated files to keep (older ones are deleted)
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+/// Log file rotation period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    /// Single file, never rotated
+    Never,
 }
 
 impl Config {
-    /// Load configuration from file
+    /// Load and validate configuration from file
     ///
     /// Environment variables override config file values:
-    /// - MQTT_PASSWORD: Override MQTT password
-    /// - INFLUXDB_TOKEN: Override InfluxDB token
+    /// - MQTT_PASSWORD: Override MQTT password (legacy)
+    /// - INFLUXDB_TOKEN: Override InfluxDB token (legacy)
+    /// - WK7__<SECTION>__<FIELD>: Override any field (see [`Config::apply_env_overrides`])
+    ///
+    /// Secrets given as `token_file` / `password_file` are read last and take
+    /// precedence over inline values.
     pub fn load(path: &str) -> Result<Self> {
+        let config = Self::from_file(path)?;
+
+        // Validate configuration
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Read configuration from file and apply environment overrides
+    ///
+    /// Unlike [`Config::load`] this doesn't validate, so callers can apply
+    /// further overrides (e.g. from the command line) before validating.
+    pub fn from_file(path: &str) -> Result<Self> {
         // Read config file
         let config_str = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path))?;
@@ -62,63 +829,616 @@ impl Config {
             config.influxdb.token = token;
         }
 
-        // Validate configuration
-        config.validate()?;
+        if let Ok(password) = std::env::var("MQTT_PASSWORD") {
+            tracing::info!("Using MQTT_PASSWORD from environment");
+            config.mqtt.password = Some(password);
+        }
+
+        let overridden = config.apply_env_overrides(std::env::vars())?;
+        if !overridden.is_empty() {
+            tracing::info!(fields = ?overridden, "Applied environment overrides");
+        }
+
+        config.resolve_secret_files()?;
 
         Ok(config)
     }
 
+    /// Queue settings for a sink (defaults if it has no `[queues.<sink>]`)
+    pub fn queue(&self, sink: &str) -> QueueConfig {
+        self.queues.get(sink).cloned().unwrap_or_default()
+    }
+
+    /// Copy of `self` with the runtime-reloadable settings taken from `new`
+    ///
+    /// Settings that need a restart keep their current values, so the
+    /// result always describes what the running pipeline actually uses.
+    pub fn reloadable_from(&self, new: &Config) -> Config {
+        let mut applied = new.clone();
+        applied.gateway = self.gateway.clone();
+        applied.influxdb = InfluxDbConfig {
+            batch_size: new.influxdb.batch_size,
+            flush_interval_ms: new.influxdb.flush_interval_ms,
+            ..self.influxdb.clone()
+        };
+        applied.http = self.http.clone();
+        applied.sinks = self.sinks.clone();
+        applied.queues = self.queues.clone();
+        applied.mqtt = MqttConfig {
+            topic_prefix: new.mqtt.topic_prefix.clone(),
+            qos: new.mqtt.qos,
+            ..self.mqtt.clone()
+        };
+        applied
+    }
+
+    /// List changed fields that only take effect after a restart
+    ///
+    /// Everything else (topic prefix, QoS, log level, InfluxDB batching,
+    /// processing settings) is applied to the running pipeline on reload.
+    pub fn restart_required_changes(&self, new: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name: &'static str, differs: bool| {
+            if differs {
+                changed.push(name);
+            }
+        };
+
+        check("mqtt.broker_url", self.mqtt.broker_url != new.mqtt.broker_url);
+        check("mqtt.client_id", self.mqtt.client_id != new.mqtt.client_id);
+        check(
+            "mqtt.username/password",
+            self.mqtt.username != new.mqtt.username || self.mqtt.password != new.mqtt.password,
+        );
+        check("influxdb.url", self.influxdb.url != new.influxdb.url);
+        check("influxdb.org", self.influxdb.org != new.influxdb.org);
+        check("influxdb.bucket", self.influxdb.bucket != new.influxdb.bucket);
+        check("influxdb.token", self.influxdb.token != new.influxdb.token);
+        check("influxdb.downsampling", self.influxdb.downsampling != new.influxdb.downsampling);
+        check("gateway.probe_id", self.gateway.probe_id != new.gateway.probe_id);
+        check("gateway.chip", self.gateway.chip != new.gateway.chip);
+        check("gateway.firmware_path", self.gateway.firmware_path != new.gateway.firmware_path);
+        check(
+            "gateway.channel_capacity",
+            self.gateway.channel_capacity != new.gateway.channel_capacity,
+        );
+        check(
+            "gateway.restart_on_exit/restart_delay_secs",
+            self.gateway.restart_on_exit != new.gateway.restart_on_exit
+                || self.gateway.restart_delay_secs != new.gateway.restart_delay_secs,
+        );
+        check(
+            "http",
+            self.http.enabled != new.http.enabled
+                || self.http.listen_addr != new.http.listen_addr
+                || self.http.ready_timeout_secs != new.http.ready_timeout_secs,
+        );
+        check("sinks", self.sinks != new.sinks);
+        check("queues", self.queues != new.queues);
+
+        changed
+    }
+
+    /// Replace secrets with the contents of their `*_file` counterparts
+    ///
+    /// Surrounding whitespace (such as the trailing newline most secret
+    /// files have) is trimmed.
+    pub fn resolve_secret_files(&mut self) -> Result<()> {
+        if let Some(path) = &self.influxdb.token_file {
+            self.influxdb.token = read_secret_file(path)
+                .context("Failed to read influxdb.token_file")?;
+            tracing::info!(path = %path, "Loaded InfluxDB token from file");
+        }
+
+        if let Some(path) = &self.mqtt.password_file {
+            self.mqtt.password = Some(
+                read_secret_file(path).context("Failed to read mqtt.password_file")?,
+            );
+            tracing::info!(path = %path, "Loaded MQTT password from file");
+        }
+
+        Ok(())
+    }
+
+    /// Apply `WK7__<SECTION>__<FIELD>` overrides from the given variables
+    ///
+    /// Each value is parsed according to the type of the field it replaces
+    /// (string, integer, float, boolean, or a TOML array/table literal), and
+    /// unknown fields are rejected. All problems are reported together.
+    ///
+    /// Returns the overridden fields as `path=value`, with secrets redacted,
+    /// suitable for logging.
+    pub fn apply_env_overrides<I>(&mut self, vars: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut overrides: Vec<(String, Vec<String>, String)> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let path = name.strip_prefix(ENV_PREFIX)?;
+                let segments = path
+                    .split(ENV_SEPARATOR)
+                    .map(|s| s.to_ascii_lowercase())
+                    .collect::<Vec<_>>();
+                Some((name, segments, value))
+            })
+            .collect();
+
+        if overrides.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Apply in a stable order so error messages are deterministic
+        overrides.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let base = toml::Value::try_from(&*self).context("Failed to serialize configuration")?;
+        let mut root = base.clone();
+        let mut errors = Vec::new();
+        let mut applied = Vec::new();
+
+        for (name, segments, raw) in &overrides {
+            let path = segments.join(".");
+            if segments.iter().any(|s| s.is_empty()) {
+                errors.push(format!("{}: malformed variable name", name));
+                continue;
+            }
+
+            match set_toml_path(&mut root, &base, segments, raw) {
+                Ok(()) => applied.push(path),
+                Err(e) => errors.push(format!("{} ({}): {}", name, path, e)),
+            }
+        }
+
+        if errors.is_empty() {
+            match root.clone().try_into::<Config>() {
+                Ok(updated) => {
+                    // Fields serde doesn't know about are silently dropped on
+                    // deserialize, so check every override survived the round trip
+                    let check = toml::Value::try_from(&updated)
+                        .context("Failed to serialize configuration")?;
+                    for (name, segments, _) in &overrides {
+                        if lookup_toml_path(&check, segments).is_none() {
+                            errors.push(format!("{}: unknown field {}", name, segments.join(".")));
+                        }
+                    }
+                    if errors.is_empty() {
+                        *self = updated;
+                    }
+                }
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid environment overrides:\n  {}", errors.join("\n  "));
+        }
+
+        Ok(applied
+            .into_iter()
+            .zip(overrides.iter())
+            .map(|(path, (_, _, raw))| {
+                if is_secret_key(&path) {
+                    format!("{}=<redacted>", path)
+                } else {
+                    format!("{}={}", path, raw)
+                }
+            })
+            .collect())
+    }
+
     /// Validate configuration values
-    fn validate(&self) -> Result<()> {
-        // Validate MQTT QoS
+    ///
+    /// Reports every problem at once (see [`ValidationErrors`]). Doesn't
+    /// touch the filesystem; use [`Config::validate_deployment`] for that.
+    pub fn validate(&self) -> Result<()> {
+        ValidationErrors::check(self.validation_errors())
+    }
+
+    /// Validate configuration values and the files the gateway needs to run
+    pub fn validate_deployment(&self) -> Result<()> {
+        let mut errors = self.validation_errors();
+        errors.extend(self.deployment_errors());
+        ValidationErrors::check(errors)
+    }
+
+    /// Collect every static configuration problem
+    pub fn validation_errors(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let mut err = |path: &str, message: String| {
+            errors.push(ValidationError {
+                path: path.to_string(),
+                message,
+            });
+        };
+
+        // MQTT
+        // Also run the client's own parser, so what passes here connects
+        let broker_url = check_url(&self.mqtt.broker_url, &["mqtt://", "mqtts://"]).and_then(|()| {
+            crate::mqtt::parse_broker_url(&self.mqtt.broker_url)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        });
+        if let Err(message) = broker_url {
+            err("mqtt.broker_url", message);
+        }
+        if self.mqtt.client_id.is_empty() {
+            err("mqtt.client_id", "must not be empty".to_string());
+        }
+        if let Err(message) = check_topic_prefix(&self.mqtt.topic_prefix) {
+            err("mqtt.topic_prefix", message);
+        }
         if self.mqtt.qos > 2 {
-            anyhow::bail!("Invalid MQTT QoS level: {} (must be 0, 1, or 2)", self.mqtt.qos);
+            err("mqtt.qos", format!("invalid level {} (must be 0, 1, or 2)", self.mqtt.qos));
+        }
+        if self.mqtt.password.is_some() && self.mqtt.username.is_none() {
+            err("mqtt.username", "required when a password is set".to_string());
         }
 
-        // Validate URLs
-        if !self.mqtt.broker_url.starts_with("mqtt://") && !self.mqtt.broker_url.starts_with("mqtts://") {
-            anyhow::bail!("Invalid MQTT broker URL: {} (must start with mqtt:// or mqtts://)", self.mqtt.broker_url);
+        // InfluxDB
+        if let Err(message) = check_url(&self.influxdb.url, &["http://", "https://"]) {
+            err("influxdb.url", message);
+        }
+        if self.influxdb.org.trim().is_empty() {
+            err("influxdb.org", "must not be empty".to_string());
+        }
+        if self.influxdb.bucket.trim().is_empty() {
+            err("influxdb.bucket", "must not be empty".to_string());
+        }
+        if self.influxdb.token.trim().is_empty() {
+            err("influxdb.token", "must not be empty (set token or token_file)".to_string());
+        }
+        if self.influxdb.batch_size == 0 {
+            err("influxdb.batch_size", "must be at least 1".to_string());
         }
 
-        if !self.influxdb.url.starts_with("http://") && !self.influxdb.url.starts_with("https://") {
-            anyhow::bail!("Invalid InfluxDB URL: {} (must start with http:// or https://)", self.influxdb.url);
+        if let Some(ds) = self.influxdb.downsampling.as_ref().filter(|d| d.enabled) {
+            if ds.bucket.trim().is_empty() {
+                err("influxdb.downsampling.bucket", "must not be empty".to_string());
+            } else if ds.bucket == self.influxdb.bucket {
+                err(
+                    "influxdb.downsampling.bucket",
+                    format!("must differ from the raw bucket ({})", ds.bucket),
+                );
+            }
+            if ds.windows.is_empty() {
+                err("influxdb.downsampling.windows", "must not be empty".to_string());
+            }
+            for window in ds.windows.iter().filter(|w| !is_flux_duration(w)) {
+                err(
+                    "influxdb.downsampling.windows",
+                    format!("invalid window {:?} (expected e.g. 1m, 1h, 1d)", window),
+                );
+            }
         }
 
-        // Validate channel capacity
+        // Gateway
+        if self.gateway.probe_id.trim().is_empty() {
+            err("gateway.probe_id", "must not be empty".to_string());
+        }
+        if self.gateway.chip.trim().is_empty() {
+            err("gateway.chip", "must not be empty".to_string());
+        }
+        if self.gateway.firmware_path.trim().is_empty() {
+            err("gateway.firmware_path", "must not be empty".to_string());
+        }
         if self.gateway.channel_capacity == 0 {
-            anyhow::bail!("Gateway channel_capacity must be greater than 0");
+            err("gateway.channel_capacity", "must be greater than 0".to_string());
+        }
+
+        // HTTP server
+        if self.http.enabled && self.http.listen_addr.parse::<std::net::SocketAddr>().is_err() {
+            err(
+                "http.listen_addr",
+                format!("invalid address {:?} (expected e.g. 0.0.0.0:9108)", self.http.listen_addr),
+            );
+        }
+
+        if self.http.ready_timeout_secs == 0 {
+            err("http.ready_timeout_secs", "must be greater than 0".to_string());
+        }
+
+        // Validation rules
+        for (key, rule) in &self.validation.rules {
+            let path = |field: &str| format!("validation.rules.{}.{}", key, field);
+            if let (Some(min), Some(max)) = (rule.min, rule.max) {
+                if min >= max {
+                    err(&path("min"), format!("must be less than max ({} >= {})", min, max));
+                }
+            }
+            if rule.max_rate_per_sec.is_some_and(|r| r <= 0.0) {
+                err(&path("max_rate_per_sec"), "must be greater than 0".to_string());
+            }
+            if rule.stuck_count.is_some_and(|n| n < 2) {
+                err(&path("stuck_count"), "must be at least 2".to_string());
+            }
+        }
+
+        // Calibration
+        for (key, calibration) in &self.calibration {
+            let path = |field: &str| format!("calibration.{}.{}", key, field);
+            let parts: Vec<&str> = key.split('/').collect();
+            if parts.len() != 2 || parts.iter().any(|p| p.is_empty()) {
+                err(&format!("calibration.{}", key), "key must be \"node/metric\"".to_string());
+            }
+            if calibration.gain == Some(0.0) {
+                err(&path("gain"), "must not be 0".to_string());
+            }
+            if let Some(polynomial) = &calibration.polynomial {
+                if polynomial.is_empty() {
+                    err(&path("polynomial"), "must have at least one coefficient".to_string());
+                }
+                if calibration.gain.is_some() || calibration.offset.is_some() {
+                    err(&path("polynomial"), "can't be combined with gain or offset".to_string());
+                }
+            }
         }
 
+        // Aggregation
+        if self.aggregation.window_secs == 0 {
+            err("aggregation.window_secs", "must be greater than 0".to_string());
+        }
+
+        // Sinks
+        let mut names = BTreeSet::new();
+        // Types are checked against the sink registry (see crate::sink)
+        for (i, sink) in self.sinks.iter().enumerate() {
+            if !names.insert(sink.name()) {
+                err(&format!("sinks[{}].name", i), format!("duplicate sink name {:?}", sink.name()));
+            }
+        }
+        for (sink, queue) in &self.queues {
+            if !names.contains(sink.as_str()) {
+                err(&format!("queues.{}", sink), "no sink with this name".to_string());
+            }
+            if queue.capacity == 0 {
+                err(&format!("queues.{}.capacity", sink), "must be greater than 0".to_string());
+            }
+        }
+
+        // MQTT deadband
+        if self.mqtt_deadband.max_silence_secs == 0 {
+            err("mqtt_deadband.max_silence_secs", "must be greater than 0".to_string());
+        }
+        for (key, rule) in &self.mqtt_deadband.rules {
+            let thresholds = [("absolute", rule.absolute), ("percent", rule.percent)];
+            for (field, value) in thresholds {
+                if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                    err(&format!("mqtt_deadband.rules.{}.{}", key, field), "must be 0 or more".to_string());
+                }
+            }
+        }
+
+        // Link analytics
+        if self.link.window == 0 {
+            err("link.window", "must be greater than 0".to_string());
+        }
+        if !(7..=12).contains(&self.link.spreading_factor) {
+            err(
+                "link.spreading_factor",
+                format!("invalid spreading factor {} (must be 7-12)", self.link.spreading_factor),
+            );
+        }
+        if !self.link.bandwidth_khz.is_finite() || self.link.bandwidth_khz <= 0.0 {
+            err("link.bandwidth_khz", "must be greater than 0".to_string());
+        }
+
+        // Alerts
+        if let Some(url) = &self.alerts.webhook_url {
+            if let Err(message) = check_url(url, &["http://", "https://"]) {
+                err("alerts.webhook_url", message);
+            }
+        }
+        if self.alerts.webhook_timeout_secs == 0 {
+            err("alerts.webhook_timeout_secs", "must be greater than 0".to_string());
+        }
+        for (name, rule) in &self.alerts.rules {
+            let path = |field: &str| format!("alerts.rules.{}.{}", name, field);
+            if name.is_empty() || name.contains(['/', '+', '#']) {
+                err(
+                    &format!("alerts.rules.{}", name),
+                    "rule name must be a single MQTT topic level (no /, + or #)".to_string(),
+                );
+            }
+            if let Err(message) = rule.when.parse::<crate::alerts::Condition>() {
+                err(&path("when"), message);
+            }
+            if !rule.hysteresis.is_finite() || rule.hysteresis < 0.0 {
+                err(&path("hysteresis"), "must not be negative".to_string());
+            }
+        }
+
+        // Availability
+        if self.availability.offline_timeout_secs == 0 {
+            err("availability.offline_timeout_secs", "must be greater than 0".to_string());
+        }
+        for (node, secs) in &self.availability.node_timeouts {
+            if *secs == 0 {
+                err(&format!("availability.node_timeouts.{}", node), "must be greater than 0".to_string());
+            }
+        }
+
+        if self.derived.iaq_gas_baseline_ohms.is_some_and(|b| b <= 0.0) {
+            err("derived.iaq_gas_baseline_ohms", "must be greater than 0".to_string());
+        }
+
+        // Logging
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            err("logging.level", format!("invalid filter {:?}: {}", self.logging.level, e));
+        }
+        let fw_level = &self.logging.firmware_mqtt_level;
+        if fw_level != "off" && fw_level.parse::<tracing::Level>().is_err() {
+            err(
+                "logging.firmware_mqtt_level",
+                format!("invalid level {:?} (expected off, error, warn, info, debug or trace)", fw_level),
+            );
+        }
+        if let Some(file) = &self.logging.file {
+            if file.path.trim().is_empty() {
+                err("logging.file.path", "must not be empty".to_string());
+            }
+            if file.max_files == 0 {
+                err("logging.file.max_files", "must be greater than 0".to_string());
+            }
+        }
+
+        errors
+    }
+
+    /// Collect problems with files the configuration points at
+    pub fn deployment_errors(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        let firmware = std::path::Path::new(&self.gateway.firmware_path);
+        if !self.gateway.firmware_path.trim().is_empty() && !firmware.is_file() {
+            errors.push(ValidationError {
+                path: "gateway.firmware_path".to_string(),
+                message: format!("file not found: {} (build the firmware first)", firmware.display()),
+            });
+        }
+
+        errors
+    }
+}
+
+/// A single configuration problem, located by its TOML path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// TOML path of the offending field, e.g. "mqtt.broker_url"
+    pub path: String,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every problem found in a configuration, reported together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    /// `Ok` if there are no errors, otherwise all of them as one error
+    pub fn check(errors: Vec<ValidationError>) -> Result<()> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors).into())
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} configuration error(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
         Ok(())
     }
 }
 
+impl std::error::Error for ValidationErrors {}
+
+/// Check a URL's scheme, host and port
+fn check_url(url: &str, schemes: &[&str]) -> std::result::Result<(), String> {
+    let rest = schemes
+        .iter()
+        .find_map(|scheme| url.strip_prefix(scheme))
+        .ok_or_else(|| format!("{:?} must start with {}", url, schemes.join(" or ")))?;
+
+    // Drop any path, then split host and port
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+
+    if host.is_empty() {
+        return Err(format!("{:?} has no host", url));
+    }
+
+    if let Some(port) = port {
+        match port.parse::<u32>() {
+            Ok(p) if (1..=65535).contains(&p) => {}
+            Ok(p) => return Err(format!("port out of range: {}", p)),
+            Err(_) => return Err(format!("invalid port: {:?}", port)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Check an MQTT topic prefix: no wildcards, no empty levels
+fn check_topic_prefix(prefix: &str) -> std::result::Result<(), String> {
+    if prefix.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if prefix.contains(['+', '#']) {
+        return Err(format!("{:?} must not contain MQTT wildcards (+ or #)", prefix));
+    }
+    if prefix.contains('\0') {
+        return Err("must not contain NUL characters".to_string());
+    }
+    if prefix.starts_with('/') || prefix.ends_with('/') || prefix.contains("//") {
+        return Err(format!("{:?} must not have empty topic levels", prefix));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_config_validation() {
-        let mut config = Config {
+    fn test_config() -> Config {
+        Config {
             mqtt: MqttConfig {
                 broker_url: "mqtt://localhost:1883".to_string(),
                 client_id: "test".to_string(),
                 topic_prefix: "iiot".to_string(),
                 qos: 1,
+                username: None,
+                password: None,
+                password_file: None,
             },
             influxdb: InfluxDbConfig {
                 url: "http://localhost:8086".to_string(),
                 org: "test-org".to_string(),
                 bucket: "test-bucket".to_string(),
                 token: "test-token".to_string(),
+                token_file: None,
+                downsampling: None,
+                batch_size: default_batch_size(),
+                flush_interval_ms: default_flush_interval_ms(),
             },
             gateway: GatewayConfig {
                 probe_id: "test-probe".to_string(),
                 chip: "STM32F446RETx".to_string(),
                 firmware_path: "test.bin".to_string(),
                 channel_capacity: 100,
+                restart_on_exit: false,
+                restart_delay_secs: 5,
             },
-        };
+            logging: LoggingConfig::default(),
+            http: HttpConfig::default(),
+            validation: ValidationConfig::default(),
+            derived: DerivedConfig::default(),
+            calibration: BTreeMap::new(),
+            alerts: AlertsConfig::default(),
+            availability: AvailabilityConfig::default(),
+            link: LinkConfig::default(),
+            aggregation: AggregationConfig::default(),
+            mqtt_deadband: DeadbandConfig::default(),
+            sinks: default_sinks(),
+            queues: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let mut config = test_config();
 
         // Valid config should pass
         assert!(config.validate().is_ok());
@@ -131,6 +1451,9 @@ mod tests {
         // Invalid MQTT URL should fail
         config.mqtt.broker_url = "invalid://localhost".to_string();
         assert!(config.validate().is_err());
+        // The MQTT client can't connect to a URL with a path
+        config.mqtt.broker_url = "mqtt://localhost:1883/".to_string();
+        assert!(config.validate().is_err());
         config.mqtt.broker_url = "mqtt://localhost:1883".to_string();
 
         // Invalid InfluxDB URL should fail
@@ -141,5 +1464,229 @@ mod tests {
         // Zero channel capacity should fail
         config.gateway.channel_capacity = 0;
         assert!(config.validate().is_err());
+        config.gateway.channel_capacity = 100;
+
+        // Downsampling into the raw bucket should fail
+        config.influxdb.downsampling = Some(DownsamplingConfig {
+            enabled: true,
+            bucket: "test-bucket".to_string(),
+            retention_days: 365,
+            windows: default_downsampling_windows(),
+            task_prefix: default_task_prefix(),
+        });
+        assert!(config.validate().is_err());
+
+        // Separate long-term bucket should pass
+        if let Some(ds) = config.influxdb.downsampling.as_mut() {
+            ds.bucket = "test-longterm".to_string();
+        }
+        assert!(config.validate().is_ok());
+
+        // Malformed window should fail
+        if let Some(ds) = config.influxdb.downsampling.as_mut() {
+            ds.windows = vec!["1 minute".to_string()];
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = test_config();
+        let vars = vec![
+            ("WK7__MQTT__BROKER_URL".to_string(), "mqtt://broker:1884".to_string()),
+            ("WK7__GATEWAY__CHANNEL_CAPACITY".to_string(), "250".to_string()),
+            ("WK7__INFLUXDB__TOKEN".to_string(), "s3cret".to_string()),
+            ("WK7__INFLUXDB__DOWNSAMPLING__BUCKET".to_string(), "longterm".to_string()),
+            ("WK7__INFLUXDB__DOWNSAMPLING__WINDOWS".to_string(), r#"["5m"]"#.to_string()),
+            ("WK7__LOGGING__FORMAT".to_string(), "json".to_string()),
+            ("WK7__MQTT__PASSWORD".to_string(), "123456".to_string()),
+            ("WK7__DERIVED__IAQ_GAS_BASELINE_OHMS".to_string(), "250000.0".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+
+        let applied = config.apply_env_overrides(vars).unwrap();
+        assert_eq!(config.mqtt.broker_url, "mqtt://broker:1884");
+        assert_eq!(config.gateway.channel_capacity, 250);
+        assert_eq!(config.influxdb.token, "s3cret");
+        let ds = config.influxdb.downsampling.as_ref().unwrap();
+        assert_eq!(ds.bucket, "longterm");
+        assert_eq!(ds.windows, vec!["5m".to_string()]);
+        assert_eq!(config.logging.format, LogFormat::Json);
+        // Unset optional fields: strings stay strings, numbers are parsed
+        assert_eq!(config.mqtt.password.as_deref(), Some("123456"));
+        assert_eq!(config.derived.iaq_gas_baseline_ohms, Some(250000.0));
+        assert!(applied.contains(&"influxdb.token=<redacted>".to_string()));
+        assert!(applied.contains(&"gateway.channel_capacity=250".to_string()));
+
+        // Type mismatches and unknown fields are all reported, config untouched
+        let vars = vec![
+            ("WK7__GATEWAY__CHANNEL_CAPACITY".to_string(), "lots".to_string()),
+            ("WK7__MQTT__BROKER".to_string(), "mqtt://x".to_string()),
+        ];
+        let err = config.apply_env_overrides(vars).unwrap_err().to_string();
+        assert!(err.contains("WK7__GATEWAY__CHANNEL_CAPACITY"));
+        assert_eq!(config.gateway.channel_capacity, 250);
+
+        let vars = vec![("WK7__MQTT__BROKER".to_string(), "mqtt://x".to_string())];
+        let err = config.apply_env_overrides(vars).unwrap_err().to_string();
+        assert!(err.contains("unknown field mqtt.broker"));
+    }
+
+    #[test]
+    fn test_secret_files_and_redaction() {
+        let dir = std::env::temp_dir().join(format!("wk7-secrets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let token_path = dir.join("influx_token");
+        let password_path = dir.join("mqtt_password");
+        fs::write(&token_path, "file-token\n").unwrap();
+        fs::write(&password_path, "file-password\n").unwrap();
+
+        let mut config = test_config();
+        config.influxdb.token = String::new();
+        config.influxdb.token_file = Some(token_path.to_string_lossy().into_owned());
+        config.mqtt.username = Some("gateway".to_string());
+        config.mqtt.password_file = Some(password_path.to_string_lossy().into_owned());
+
+        config.resolve_secret_files().unwrap();
+        assert_eq!(config.influxdb.token, "file-token");
+        assert_eq!(config.mqtt.password.as_deref(), Some("file-password"));
+        assert!(config.validate().is_ok());
+
+        // Debug output never contains the secrets
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("file-token"));
+        assert!(!debug.contains("file-password"));
+        assert!(debug.contains(REDACTED));
+
+        // Missing secret file is an error
+        config.influxdb.token_file = Some(dir.join("missing").to_string_lossy().into_owned());
+        assert!(config.resolve_secret_files().is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_validation_reports_all_errors() {
+        let mut config = test_config();
+        config.mqtt.broker_url = "mqtt://localhost:99999".to_string();
+        config.mqtt.topic_prefix = "iiot/#".to_string();
+        config.influxdb.org = String::new();
+        config.influxdb.token = " ".to_string();
+        config.gateway.channel_capacity = 0;
+        config.logging.firmware_mqtt_level = "loud".to_string();
+        config.validation.rules.get_mut("humidity").unwrap().min = Some(100.0);
+        config.calibration.insert("temperature".to_string(), Calibration::default());
+
+        let paths: Vec<String> = config.validation_errors().into_iter().map(|e| e.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "mqtt.broker_url",
+                "mqtt.topic_prefix",
+                "influxdb.org",
+                "influxdb.token",
+                "gateway.channel_capacity",
+                "validation.rules.humidity.min",
+                "calibration.temperature",
+                "logging.firmware_mqtt_level",
+            ]
+        );
+
+        let err = config.validate().unwrap_err();
+        let errors = err.downcast_ref::<ValidationErrors>().unwrap();
+        assert_eq!(errors.0.len(), 8);
+        assert_eq!(errors.0[0].to_string(), "mqtt.broker_url: port out of range: 99999");
+
+        // Missing firmware is only a deployment error
+        let config = test_config();
+        assert!(config.validate().is_ok());
+        assert!(config.validate_deployment().is_err());
+    }
+
+    #[test]
+    fn test_sinks() {
+        #[derive(Deserialize)]
+        struct Doc {
+            sinks: Vec<SinkConfig>,
+        }
+        let doc: Doc = toml::from_str(
+            r#"
+            [[sinks]]
+            type = "mqtt"
+
+            [[sinks]]
+            type = "influxdb"
+            name = "archive"
+            enabled = false
+            batch = 10
+            "#,
+        )
+        .unwrap();
+        assert_eq!((doc.sinks[0].name(), doc.sinks[1].name()), ("mqtt", "archive"));
+        assert!(!doc.sinks[1].enabled);
+        assert_eq!(doc.sinks[1].options["batch"].as_integer(), Some(10));
+
+        let mut config = test_config();
+        config.sinks = doc.sinks;
+        config.queues.insert("archive".to_string(), QueueConfig::default());
+        assert!(config.validate().is_ok());
+
+        config.sinks.push(SinkConfig::new("mqtt"));
+        config.queues.insert("influxdb".to_string(), QueueConfig::default());
+        let paths: Vec<String> = config.validation_errors().into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, vec!["sinks[2].name", "queues.influxdb"]);
+    }
+
+    #[test]
+    fn test_check_url() {
+        assert!(check_url("mqtt://localhost", &["mqtt://"]).is_ok());
+        assert!(check_url("http://localhost:8086/", &["http://"]).is_ok());
+        assert!(check_url("mqtt://:1883", &["mqtt://"]).is_err());
+        assert!(check_url("mqtt://host:0", &["mqtt://"]).is_err());
+        assert!(check_url("mqtt://host:abc", &["mqtt://"]).is_err());
+        assert!(check_url("tcp://host", &["mqtt://", "mqtts://"]).is_err());
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let old = test_config();
+        let mut new = old.clone();
+
+        // Runtime-reloadable settings need no restart
+        new.mqtt.topic_prefix = "site-b".to_string();
+        new.mqtt.qos = 0;
+        new.logging.level = "debug".to_string();
+        new.influxdb.batch_size = 50;
+        assert!(old.restart_required_changes(&new).is_empty());
+        let applied = old.reloadable_from(&new);
+        assert_eq!(applied.mqtt.topic_prefix, "site-b");
+        assert_eq!(applied.logging.level, "debug");
+        assert_eq!(applied.influxdb.batch_size, 50);
+
+        new.mqtt.broker_url = "mqtt://other:1883".to_string();
+        new.influxdb.url = "http://other:8086".to_string();
+        new.gateway.firmware_path = "other.elf".to_string();
+        assert_eq!(
+            old.restart_required_changes(&new),
+            vec!["mqtt.broker_url", "influxdb.url", "gateway.firmware_path"]
+        );
+        let applied = old.reloadable_from(&new);
+        assert_eq!(applied.mqtt.broker_url, old.mqtt.broker_url);
+        assert_eq!(applied.influxdb.url, old.influxdb.url);
+        assert_eq!(applied.influxdb.batch_size, 50);
+        assert_eq!(applied.gateway.firmware_path, old.gateway.firmware_path);
+    }
+
+    #[test]
+    fn test_is_flux_duration() {
+        assert!(is_flux_duration("1m"));
+        assert!(is_flux_duration("1h"));
+        assert!(is_flux_duration("30s"));
+        assert!(!is_flux_duration("m"));
+        assert!(!is_flux_duration("0m"));
+        assert!(!is_flux_duration("1w"));
+        assert!(!is_flux_duration(""));
+        assert!(!is_flux_duration("1µ"));
+        assert!(!is_flux_duration("µ"));
     }
 }
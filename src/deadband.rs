@@ -0,0 +1,140 @@
+//! Report-by-exception for MQTT
+//!
+//! With `[mqtt_deadband]` enabled, a metric is only republished when it
+//! moved by more than its deadband since the last published value, its
+//! quality flag changed, or nothing was published for `max_silence_secs`
+//! (heartbeat, so subscribers can tell a quiet value from a dead gateway).
+//! Rules are keyed like validation rules: `metric` or `node/metric`;
+//! metrics without a rule are published whenever their value changes.
+//!
+//! InfluxDB is unaffected and still gets every value.
+
+use crate::config::{DeadbandConfig, DeadbandRule};
+use crate::telemetry::{Metric, Quality};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+struct Published {
+    /// Last value published to the value topic (None if it was withheld)
+    value: Option<f64>,
+    quality: Option<Quality>,
+    at: Instant,
+}
+
+/// Last published state of every topic
+#[derive(Default)]
+pub struct DeadbandFilter {
+    published: HashMap<(String, String), Published>,
+}
+
+impl DeadbandFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `metric` changed enough since it was last published
+    pub fn changed(&self, node: &str, metric: &Metric, config: &DeadbandConfig, now: Instant) -> bool {
+        if !config.enabled {
+            return true;
+        }
+        let Some(last) = self.published.get(&(node.to_string(), metric.name.clone())) else {
+            return true;
+        };
+        if now.duration_since(last.at) >= Duration::from_secs(config.max_silence_secs) || last.quality != metric.quality
+        {
+            return true;
+        }
+        let Some(last_value) = last.value else {
+            return true;
+        };
+
+        let rule = config
+            .rules
+            .get(&format!("{}/{}", node, metric.name))
+            .or_else(|| config.rules.get(&metric.name));
+        match rule {
+            Some(rule) => exceeds(rule, last_value, metric.value),
+            None => metric.value != last_value,
+        }
+    }
+
+    /// Record what was published; `value` is None if only the quality topic was
+    pub fn record(&mut self, node: &str, metric: &Metric, value: Option<f64>, now: Instant) {
+        let key = (node.to_string(), metric.name.clone());
+        let value = value.or_else(|| self.published.get(&key).and_then(|p| p.value));
+        self.published.insert(
+            key,
+            Published {
+                value,
+                quality: metric.quality,
+                at: now,
+            },
+        );
+    }
+}
+
+/// Change beyond the absolute or percentage deadband (any change if neither is set)
+fn exceeds(rule: &DeadbandRule, last: f64, value: f64) -> bool {
+    let change = (value - last).abs();
+    match (rule.absolute, rule.percent) {
+        (None, None) => change > 0.0,
+        (absolute, percent) => {
+            absolute.is_some_and(|a| change > a) || percent.is_some_and(|p| change > last.abs() * p / 100.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::{MetricKind, Reading};
+    use std::collections::BTreeMap;
+
+    fn metric(name: &str, value: f64) -> Metric {
+        Reading::new("node1", 0)
+            .with_metric(name, value, None, MetricKind::Sensor)
+            .metrics
+            .remove(0)
+    }
+
+    #[test]
+    fn test_deadband() {
+        let config = DeadbandConfig {
+            enabled: true,
+            max_silence_secs: 300,
+            rules: BTreeMap::from([
+                ("temperature".to_string(), DeadbandRule { absolute: Some(0.2), percent: None }),
+                ("node1/gas_resistance".to_string(), DeadbandRule { absolute: None, percent: Some(5.0) }),
+            ]),
+        };
+        let mut filter = DeadbandFilter::new();
+        let start = Instant::now();
+        let publish = |filter: &mut DeadbandFilter, m: &Metric, secs| {
+            let now = start + Duration::from_secs(secs);
+            let changed = filter.changed("node1", m, &config, now);
+            if changed {
+                filter.record("node1", m, Some(m.value), now);
+            }
+            changed
+        };
+
+        assert!(publish(&mut filter, &metric("temperature", 21.0), 0));
+        assert!(!publish(&mut filter, &metric("temperature", 21.15), 10));
+        assert!(publish(&mut filter, &metric("temperature", 21.3), 20));
+
+        assert!(publish(&mut filter, &metric("gas_resistance", 100_000.0), 0));
+        assert!(!publish(&mut filter, &metric("gas_resistance", 104_000.0), 10));
+        assert!(publish(&mut filter, &metric("gas_resistance", 94_000.0), 20));
+
+        // No rule: any change
+        assert!(publish(&mut filter, &metric("humidity", 40.0), 0));
+        assert!(!publish(&mut filter, &metric("humidity", 40.0), 10));
+        assert!(publish(&mut filter, &metric("humidity", 40.1), 20));
+
+        // Quality change and heartbeat
+        let mut suspect = metric("humidity", 40.1);
+        suspect.quality = Some(Quality::Suspect);
+        assert!(publish(&mut filter, &suspect, 30));
+        assert!(publish(&mut filter, &suspect, 330));
+    }
+}
@@ -0,0 +1,208 @@
+//! Derived environmental metrics
+//!
+//! Computed per reading from metrics the node already reports, then published
+//! and written like any other sensor metric:
+//! - `dew_point` (°C) and `absolute_humidity` (g/m³) from `temperature` and
+//!   `humidity` (Magnus formula)
+//! - `heat_index` (°C) from `temperature` and `humidity` (NWS formula)
+//! - `iaq` (0-500, lower is better) from `gas_resistance` and `humidity`,
+//!   estimated the way the BME680 examples do without Bosch's BSEC library
+//!
+//! Inputs flagged bad by the validation stage are ignored; a derived value
+//! inherits the worst quality of its inputs.
+
+use crate::config::DerivedConfig;
+use crate::telemetry::{MetricKind, Quality, Reading};
+use std::collections::HashMap;
+
+/// Magnus coefficients (Sonntag 1990), valid for -45 °C to 60 °C
+const MAGNUS_A: f64 = 17.62;
+const MAGNUS_B: f64 = 243.12;
+
+/// Humidity the IAQ estimate considers ideal, in percent
+const IAQ_HUMIDITY_BASELINE: f64 = 40.0;
+
+/// Share of the IAQ score that comes from gas resistance (rest: humidity)
+const IAQ_GAS_WEIGHT: f64 = 0.75;
+
+/// Dew point in °C
+pub fn dew_point(temperature: f64, humidity: f64) -> f64 {
+    let gamma = (humidity / 100.0).ln() + MAGNUS_A * temperature / (MAGNUS_B + temperature);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+/// Absolute humidity in g/m³
+pub fn absolute_humidity(temperature: f64, humidity: f64) -> f64 {
+    // Saturation vapour pressure in hPa, times the water vapour gas constant
+    let saturation = 6.112 * (MAGNUS_A * temperature / (MAGNUS_B + temperature)).exp();
+    saturation * humidity * 2.1674 / (273.15 + temperature)
+}
+
+/// Heat index (apparent temperature) in °C
+///
+/// Uses the NWS simple formula, switching to the Rothfusz regression with
+/// its low/high humidity adjustments above 80 °F. Below that the heat index
+/// is close to the air temperature.
+pub fn heat_index(temperature: f64, humidity: f64) -> f64 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity;
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.04901523 * t + 10.14333127 * rh
+            - 0.22475541 * t * rh
+            - 0.00683783 * t * t
+            - 0.05481717 * rh * rh
+            + 0.00122874 * t * t * rh
+            + 0.00085282 * t * rh * rh
+            - 0.00000199 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+        hi
+    };
+
+    (hi - 32.0) * 5.0 / 9.0
+}
+
+/// IAQ index estimate, 0 (excellent) to 500 (hazardous)
+///
+/// Scores gas resistance against a clean-air `gas_baseline` and humidity
+/// against 40 %, then maps the combined 0-100 score onto the IAQ scale.
+pub fn iaq(gas_resistance: f64, humidity: f64, gas_baseline: f64) -> f64 {
+    let gas_score = (gas_resistance / gas_baseline).min(1.0) * IAQ_GAS_WEIGHT;
+
+    let offset = humidity - IAQ_HUMIDITY_BASELINE;
+    let humidity_score = if offset > 0.0 {
+        (100.0 - IAQ_HUMIDITY_BASELINE - offset) / (100.0 - IAQ_HUMIDITY_BASELINE)
+    } else {
+        (IAQ_HUMIDITY_BASELINE + offset) / IAQ_HUMIDITY_BASELINE
+    };
+    let humidity_score = humidity_score.clamp(0.0, 1.0) * (1.0 - IAQ_GAS_WEIGHT);
+
+    (1.0 - gas_score - humidity_score) * 500.0
+}
+
+/// Stateful deriver, one per pipeline
+///
+/// Without a configured `iaq_gas_baseline_ohms`, the highest gas resistance
+/// seen from a node serves as its clean-air baseline, so IAQ reads too good
+/// until the sensor has seen clean air once.
+#[derive(Default)]
+pub struct Deriver {
+    gas_baselines: HashMap<String, f64>,
+}
+
+impl Deriver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add derived metrics to every reading that has the inputs for them
+    pub fn derive(&mut self, readings: &mut [Reading], config: &DerivedConfig) {
+        if !config.enabled {
+            return;
+        }
+
+        for reading in readings.iter_mut() {
+            let temperature = input(reading, "temperature");
+            let humidity = input(reading, "humidity");
+            let gas = input(reading, "gas_resistance");
+
+            if let (Some((t, tq)), Some((h, hq))) = (temperature, humidity) {
+                // Dew point is undefined for 0 % humidity
+                if h > 0.0 {
+                    let quality = worst(tq, hq);
+                    add(reading, "dew_point", dew_point(t, h), Some("celsius"), quality);
+                    add(reading, "absolute_humidity", absolute_humidity(t, h), Some("g_per_m3"), quality);
+                    add(reading, "heat_index", heat_index(t, h), Some("celsius"), quality);
+                }
+            }
+
+            if let (Some((g, gq)), Some((h, hq))) = (gas, humidity) {
+                let baseline = match config.iaq_gas_baseline_ohms {
+                    Some(baseline) => baseline,
+                    None => {
+                        let seen = self.gas_baselines.entry(reading.node_id.clone()).or_insert(g);
+                        *seen = seen.max(g);
+                        *seen
+                    }
+                };
+                add(reading, "iaq", iaq(g, h, baseline), None, worst(gq, hq));
+            }
+        }
+    }
+}
+
+/// Value and quality of an input metric, unless it's missing or bad
+fn input(reading: &Reading, name: &str) -> Option<(f64, Option<Quality>)> {
+    reading
+        .metric(name)
+        .filter(|m| m.quality != Some(Quality::Bad) && m.value.is_finite())
+        .map(|m| (m.value, m.quality))
+}
+
+fn worst(a: Option<Quality>, b: Option<Quality>) -> Option<Quality> {
+    a.max(b)
+}
+
+fn add(reading: &mut Reading, name: &str, value: f64, unit: Option<&str>, quality: Option<Quality>) {
+    // Round like the firmware does, derived values aren't more precise
+    let value = (value * 100.0).round() / 100.0;
+    reading.add_metric(name, value, unit, MetricKind::Sensor);
+    if let Some(metric) = reading.metrics.last_mut() {
+        metric.quality = quality;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.05
+    }
+
+    #[test]
+    fn test_formulas() {
+        assert!(close(dew_point(20.0, 50.0), 9.26));
+        assert!(close(absolute_humidity(20.0, 50.0), 8.64));
+        // Below 80 °F the heat index stays near the air temperature
+        assert!(close(heat_index(20.0, 50.0), 19.36));
+        // 90 °F at 70 % feels like 106 °F
+        assert!(close(heat_index(32.22, 70.0), 41.1));
+
+        // Clean air at 40 % is perfect, half the baseline resistance isn't
+        assert!(close(iaq(100_000.0, 40.0, 100_000.0), 0.0));
+        assert!(close(iaq(50_000.0, 40.0, 100_000.0), 187.5));
+    }
+
+    #[test]
+    fn test_derive_readings() {
+        let mut readings = vec![
+            Reading::new("node1", 0)
+                .with_metric("temperature", 20.0, Some("celsius"), MetricKind::Sensor)
+                .with_metric("humidity", 50.0, Some("percent"), MetricKind::Sensor)
+                .with_metric("gas_resistance", 50_000.0, Some("ohms"), MetricKind::Sensor),
+            Reading::new("signal", 0).with_metric("rssi", -70.0, Some("dbm"), MetricKind::Link),
+        ];
+        readings[0].metrics[0].quality = Some(Quality::Suspect);
+        readings[0].metrics[1].quality = Some(Quality::Good);
+        readings[0].metrics[2].quality = Some(Quality::Bad);
+
+        Deriver::new().derive(&mut readings, &DerivedConfig::default());
+
+        let node1 = &readings[0];
+        assert_eq!(node1.metric("dew_point").unwrap().value, 9.26);
+        assert_eq!(node1.metric("dew_point").unwrap().quality, Some(Quality::Suspect));
+        assert!(node1.metric("absolute_humidity").is_some());
+        assert!(node1.metric("heat_index").is_some());
+        // Bad gas reading: no IAQ
+        assert!(node1.metric("iaq").is_none());
+        assert_eq!(readings[1].metrics.len(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+//! Concurrent fan-out from the processor to the sinks
+//!
+//! The processor enriches each packet once and hands the result to every
+//! sink through the sink's own bounded [`SinkQueue`], drained by a worker
+//! task per sink. A slow InfluxDB write then only backs up the InfluxDB
+//! queue. When a queue is full, its [`OverflowPolicy`] decides:
+//! - `block`: wait for room (nothing lost, but the processor and with it
+//!   every other sink stall)
+//! - `drop_oldest`: discard the oldest queued event (default)
+//! - `drop_newest`: discard the incoming event
+
+use crate::availability::StatusChange;
+use crate::config::{OverflowPolicy, QueueConfig};
+use crate::metrics::Metrics;
+use crate::reboot::RebootEvent;
+use crate::telemetry::Reading;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// What the processor hands to the sinks
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkEvent {
+    /// Processed readings of one packet
+    Telemetry {
+        readings: Vec<Reading>,
+        /// When the gateway received the packet, in seconds since the epoch
+        received_secs: u64,
+    },
+    /// Detected firmware reboots
+    Reboots(Vec<RebootEvent>),
+    /// Nodes going online or offline
+    Status(Vec<StatusChange>),
+}
+
+/// Queue of one sink, shared by the processor and the sink's worker
+pub type EventQueue = SinkQueue<Arc<SinkEvent>>;
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+/// Bounded single-consumer queue with an overflow policy
+pub struct SinkQueue<T> {
+    state: Mutex<QueueState<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    items_ready: Notify,
+    space_ready: Notify,
+}
+
+impl<T> SinkQueue<T> {
+    pub fn new(config: &QueueConfig) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(config.capacity),
+                closed: false,
+            }),
+            capacity: config.capacity.max(1),
+            policy: config.overflow,
+            items_ready: Notify::new(),
+            space_ready: Notify::new(),
+        }
+    }
+
+    /// Queue an item; returns false if the overflow policy dropped one
+    pub async fn push(&self, item: T) -> bool {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.items.len() < self.capacity {
+                    state.items.push_back(item);
+                    self.items_ready.notify_one();
+                    return true;
+                }
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        state.items.pop_front();
+                        state.items.push_back(item);
+                        self.items_ready.notify_one();
+                        return false;
+                    }
+                    OverflowPolicy::DropNewest => return false,
+                    OverflowPolicy::Block => {}
+                }
+            }
+            self.space_ready.notified().await;
+        }
+    }
+
+    /// Next item, or None once the queue is closed and drained
+    ///
+    /// Cancel safe: nothing is taken from the queue unless it is returned.
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    self.space_ready.notify_one();
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.items_ready.notified().await;
+        }
+    }
+
+    /// Let the worker finish once the queue is drained
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.items_ready.notify_one();
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The processor's side of the fan-out: one queue per sink
+pub struct Fanout {
+    sinks: Vec<(String, Arc<EventQueue>)>,
+    metrics: Arc<Metrics>,
+}
+
+impl Fanout {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            sinks: Vec::new(),
+            metrics,
+        }
+    }
+
+    /// Add a sink; its worker drains the returned queue
+    pub fn add(&mut self, name: &str, config: &QueueConfig) -> Arc<EventQueue> {
+        let queue = Arc::new(SinkQueue::new(config));
+        self.sinks.push((name.to_string(), queue.clone()));
+        queue
+    }
+
+    /// Hand an event to every sink
+    pub async fn send(&self, event: SinkEvent) {
+        let event = Arc::new(event);
+        for (name, queue) in &self.sinks {
+            if !queue.push(event.clone()).await {
+                self.metrics.sink_queue_dropped.with_label_values(&[name.as_str()]).inc();
+                warn!(sink = %name, "Sink queue full, dropping event");
+            }
+            self.metrics
+                .sink_queue_depth
+                .with_label_values(&[name.as_str()])
+                .set(queue.len() as i64);
+        }
+    }
+
+    /// Close every queue (at shutdown, after the last event)
+    pub fn close(&self) {
+        for (_, queue) in &self.sinks {
+            queue.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn queue(overflow: OverflowPolicy) -> SinkQueue<u32> {
+        SinkQueue::new(&QueueConfig { capacity: 2, overflow })
+    }
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let q = queue(OverflowPolicy::DropOldest);
+        assert!(q.push(1).await && q.push(2).await);
+        assert!(!q.push(3).await);
+        q.close();
+        assert_eq!((q.pop().await, q.pop().await, q.pop().await), (Some(2), Some(3), None));
+
+        let q = queue(OverflowPolicy::DropNewest);
+        assert!(q.push(1).await && q.push(2).await);
+        assert!(!q.push(3).await);
+        assert_eq!((q.pop().await, q.pop().await), (Some(1), Some(2)));
+
+        // Block waits until the worker makes room
+        let q = Arc::new(queue(OverflowPolicy::Block));
+        assert!(q.push(1).await && q.push(2).await);
+        let producer = tokio::spawn({
+            let q = q.clone();
+            async move { q.push(3).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+        assert_eq!(q.pop().await, Some(1));
+        assert!(producer.await.unwrap());
+        assert_eq!(q.len(), 2);
+    }
+}
@@ -0,0 +1,551 @@
+//! File sink for offline analysis and local backup
+//!
+//! Writes every processed reading, one row per metric, to daily CSV files
+//! `<directory>/telemetry-YYYY-MM-DD.csv` (UTC, appended across restarts)
+//! with columns `time,node,uptime_ms,metric,value,unit,quality`.
+//!
+//! Built with the `parquet` cargo feature, `parquet = true` also writes the
+//! same rows to `telemetry-YYYY-MM-DDTHHMMSS.parquet` files (named after
+//! their first row). A file is written when the hour ends, once
+//! `parquet_max_rows` rows are pending, and at shutdown, so a busy hour is
+//! split over several files. Until then the rows are only held in memory and
+//! a crash loses them; the CSV files are flushed every second and are the
+//! backup to rely on.
+//!
+//! Files older than `retention_days` are deleted on every day change.
+//!
+//! All file I/O, including Parquet encoding, runs on blocking threads.
+
+use crate::config::{self, SinkConfig};
+use crate::fanout::SinkEvent;
+use crate::sink::{self, TelemetrySink};
+use crate::telemetry::{Quality, Reading};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// File name prefix, followed by the UTC date
+const FILE_PREFIX: &str = "telemetry-";
+
+const CSV_HEADER: &str = "time,node,uptime_ms,metric,value,unit,quality";
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// `[[sinks]]` settings for `type = "file"`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileSinkOptions {
+    /// Directory for the data files (created if missing)
+    #[serde(default = "default_directory")]
+    pub directory: PathBuf,
+    /// Write daily CSV files
+    #[serde(default = "config::default_true")]
+    pub csv: bool,
+    /// Write hourly Parquet files (needs the `parquet` feature); pending rows
+    /// are only in memory until their file is written
+    #[serde(default)]
+    pub parquet: bool,
+    /// Write a Parquet file early once this many rows are pending
+    #[serde(default = "default_parquet_max_rows")]
+    pub parquet_max_rows: usize,
+    /// Delete files older than this many days (0 = keep forever)
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+}
+
+fn default_directory() -> PathBuf {
+    PathBuf::from("data")
+}
+
+fn default_retention_days() -> u64 {
+    30
+}
+
+fn default_parquet_max_rows() -> usize {
+    100_000
+}
+
+/// One metric of one reading
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    /// Gateway receive time, seconds since the epoch
+    time_secs: u64,
+    node: String,
+    /// Firmware uptime timestamp
+    uptime_ms: u64,
+    metric: String,
+    value: f64,
+    unit: Option<String>,
+    quality: Option<&'static str>,
+}
+
+impl Row {
+    fn from_readings(readings: &[Reading], time_secs: u64) -> impl Iterator<Item = Row> + '_ {
+        readings.iter().flat_map(move |reading| {
+            reading.metrics.iter().map(move |metric| Row {
+                time_secs,
+                node: reading.node_id.clone(),
+                uptime_ms: reading.timestamp_ms,
+                metric: metric.name.clone(),
+                value: metric.value,
+                unit: metric.unit.clone(),
+                quality: metric.quality.map(Quality::as_str),
+            })
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            format_time(self.time_secs),
+            csv_field(&self.node),
+            self.uptime_ms,
+            csv_field(&self.metric),
+            self.value,
+            csv_field(self.unit.as_deref().unwrap_or("")),
+            self.quality.unwrap_or(""),
+        )
+    }
+}
+
+/// Quote a CSV field if it needs it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Calendar date of a day number since the epoch (proleptic Gregorian)
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's days-to-civil algorithm, shifted to start in March
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `YYYY-MM-DD` of a day number since the epoch
+fn format_date(days: u64) -> String {
+    let (year, month, day) = civil_date(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// RFC 3339 UTC time, e.g. `2025-01-31T12:00:05Z`
+fn format_time(secs: u64) -> String {
+    let rem = secs % SECS_PER_DAY;
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(secs / SECS_PER_DAY),
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Delete data files dated before `today - retention_days`
+fn remove_expired(directory: &Path, today: u64, retention_days: u64) -> Result<Vec<PathBuf>> {
+    let cutoff = format_date(today.saturating_sub(retention_days));
+    let mut removed = Vec::new();
+
+    for entry in fs::read_dir(directory).with_context(|| format!("Failed to list {}", directory.display()))? {
+        let path = entry?.path();
+        let Some(date) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(FILE_PREFIX))
+            .and_then(|rest| rest.get(..10))
+        else {
+            continue;
+        };
+        // ISO dates compare correctly as strings
+        if date.len() == 10 && date < cutoff.as_str() {
+            fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+            removed.push(path);
+        }
+    }
+
+    Ok(removed)
+}
+
+struct CsvFile {
+    day: u64,
+    writer: BufWriter<File>,
+}
+
+/// Writes telemetry to local files
+pub struct FileSink {
+    directory: PathBuf,
+    /// Only locked by the blocking task of the current call
+    files: Arc<Mutex<Files>>,
+}
+
+impl FileSink {
+    /// Factory for the `file` sink type
+    pub fn from_config(config: &SinkConfig) -> Result<Self> {
+        Self::new(Self::options(config)?)
+    }
+
+    /// Parse and check the options, for config validation
+    pub fn options(config: &SinkConfig) -> Result<FileSinkOptions> {
+        let options: FileSinkOptions = sink::options(config)?;
+        if options.parquet && !cfg!(feature = "parquet") {
+            anyhow::bail!("parquet = true needs the gateway built with the \"parquet\" feature");
+        }
+        if options.parquet_max_rows == 0 {
+            anyhow::bail!("parquet_max_rows must be at least 1");
+        }
+        Ok(options)
+    }
+
+    pub fn new(options: FileSinkOptions) -> Result<Self> {
+        fs::create_dir_all(&options.directory)
+            .with_context(|| format!("Failed to create {}", options.directory.display()))?;
+        info!(directory = %options.directory.display(), csv = options.csv, parquet = options.parquet, "File sink writing");
+
+        Ok(Self {
+            directory: options.directory.clone(),
+            files: Arc::new(Mutex::new(Files {
+                options,
+                csv: None,
+                pending: Vec::new(),
+                checked_day: None,
+            })),
+        })
+    }
+
+    /// Run file I/O on a blocking thread
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Files) -> Result<T> + Send + 'static,
+    {
+        let files = self.files.clone();
+        tokio::task::spawn_blocking(move || f(&mut files.lock().expect("file sink state poisoned")))
+            .await
+            .context("File sink task failed")?
+    }
+}
+
+/// Open files and buffered rows
+struct Files {
+    options: FileSinkOptions,
+    csv: Option<CsvFile>,
+    /// Rows of the current hour not yet written to Parquet (at most
+    /// `parquet_max_rows`)
+    pending: Vec<Row>,
+    /// Day of the last retention check
+    checked_day: Option<u64>,
+}
+
+impl Files {
+    fn write_rows(&mut self, rows: Vec<Row>, time_secs: u64) -> Result<()> {
+        let day = time_secs / SECS_PER_DAY;
+        if self.checked_day != Some(day) {
+            self.checked_day = Some(day);
+            if self.options.retention_days > 0 {
+                for path in remove_expired(&self.options.directory, day, self.options.retention_days)? {
+                    info!(path = %path.display(), "Deleted expired data file");
+                }
+            }
+        }
+
+        if self.options.csv {
+            let csv = self.csv_file(day)?;
+            for row in &rows {
+                writeln!(csv.writer, "{}", row.to_csv())?;
+            }
+        }
+
+        if self.options.parquet {
+            let hour = |secs: u64| secs / 3600;
+            if self.pending.first().is_some_and(|row| hour(row.time_secs) != hour(time_secs)) {
+                self.write_parquet()?;
+            }
+            self.pending.extend(rows);
+            if self.pending.len() >= self.options.parquet_max_rows {
+                self.write_parquet()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// CSV file for `day`, rotating if the day changed
+    fn csv_file(&mut self, day: u64) -> Result<&mut CsvFile> {
+        if self.csv.as_ref().is_some_and(|csv| csv.day != day) {
+            if let Some(mut old) = self.csv.take() {
+                old.writer.flush()?;
+            }
+        }
+        if self.csv.is_none() {
+            let path = self
+                .options
+                .directory
+                .join(format!("{}{}.csv", FILE_PREFIX, format_date(day)));
+            let new = !path.exists();
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let mut writer = BufWriter::new(file);
+            if new {
+                writeln!(writer, "{}", CSV_HEADER)?;
+            }
+            info!(path = %path.display(), "Writing CSV file");
+            self.csv = Some(CsvFile { day, writer });
+        }
+        Ok(self.csv.as_mut().expect("opened above"))
+    }
+
+    /// Write the pending rows to a new Parquet file
+    fn write_parquet(&mut self) -> Result<()> {
+        let Some(first) = self.pending.first() else {
+            return Ok(());
+        };
+        let name = format_time(first.time_secs).replace(':', "").trim_end_matches('Z').to_string();
+        let file_name = |n: usize| match n {
+            0 => format!("{}{}.parquet", FILE_PREFIX, name),
+            n => format!("{}{}-{}.parquet", FILE_PREFIX, name, n),
+        };
+        // Several files can start in the same second when rows pile up fast
+        let path = (0..)
+            .map(|n| self.options.directory.join(file_name(n)))
+            .find(|path| !path.exists())
+            .expect("unbounded range");
+        let rows = std::mem::take(&mut self.pending);
+        parquet_file::write(&path, &rows)?;
+        info!(path = %path.display(), rows = rows.len(), "Wrote Parquet file");
+        Ok(())
+    }
+
+    fn flush_csv(&mut self) -> Result<()> {
+        if let Some(csv) = self.csv.as_mut() {
+            csv.writer.flush().context("Failed to flush CSV file")?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TelemetrySink for FileSink {
+    async fn write(&mut self, event: &SinkEvent) -> Result<()> {
+        let SinkEvent::Telemetry {
+            readings,
+            received_secs,
+        } = event
+        else {
+            return Ok(());
+        };
+        let rows = Row::from_readings(readings, *received_secs).collect();
+        let received_secs = *received_secs;
+        self.blocking(move |files| files.write_rows(rows, received_secs))
+            .await
+            .with_context(|| format!("Failed to write telemetry to {}", self.directory.display()))
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.blocking(Files::flush_csv).await
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.blocking(|files| {
+            files.flush_csv()?;
+            if let Err(e) = files.write_parquet() {
+                warn!(error = %format!("{:#}", e), "Failed to write Parquet file at shutdown");
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use super::Row;
+    use anyhow::{Context, Result};
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampSecondArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Write rows to `path`, via a temporary file so readers never see a
+    /// partial file
+    pub fn write(path: &Path, rows: &[Row]) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("time", DataType::Timestamp(TimeUnit::Second, Some("UTC".into())), false),
+            Field::new("node", DataType::Utf8, false),
+            Field::new("uptime_ms", DataType::UInt64, false),
+            Field::new("metric", DataType::Utf8, false),
+            Field::new("value", DataType::Float64, false),
+            Field::new("unit", DataType::Utf8, true),
+            Field::new("quality", DataType::Utf8, true),
+        ]));
+        let time = TimestampSecondArray::from_iter_values(rows.iter().map(|r| r.time_secs as i64));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(time.with_timezone("UTC")),
+            Arc::new(rows.iter().map(|r| Some(r.node.as_str())).collect::<StringArray>()),
+            Arc::new(rows.iter().map(|r| r.uptime_ms).collect::<UInt64Array>()),
+            Arc::new(rows.iter().map(|r| Some(r.metric.as_str())).collect::<StringArray>()),
+            Arc::new(rows.iter().map(|r| r.value).collect::<Float64Array>()),
+            Arc::new(rows.iter().map(|r| r.unit.as_deref()).collect::<StringArray>()),
+            Arc::new(rows.iter().map(|r| r.quality).collect::<StringArray>()),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let tmp = path.with_extension("parquet.tmp");
+        let file = File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?;
+        let mut writer = ArrowWriter::try_new(file, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to rename {}", tmp.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+mod parquet_file {
+    use super::Row;
+    use anyhow::Result;
+    use std::path::Path;
+
+    /// Unreachable: `FileSink::options` rejects `parquet = true` without the feature
+    pub fn write(_path: &Path, _rows: &[Row]) -> Result<()> {
+        anyhow::bail!("built without the \"parquet\" feature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        // Leap day and the day after
+        assert_eq!(format_date(19_782), "2024-02-29");
+        assert_eq!(format_date(19_783), "2024-03-01");
+        assert_eq!(format_time(1_738_324_805), "2025-01-31T12:00:05Z");
+    }
+
+    #[tokio::test]
+    async fn test_daily_csv_and_retention() {
+        let directory = std::env::temp_dir().join(format!("wk7-file-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("telemetry-2025-01-01.csv"), "old").unwrap();
+        fs::write(directory.join("notes.txt"), "kept").unwrap();
+
+        let mut sink = FileSink::new(FileSinkOptions {
+            directory: directory.clone(),
+            csv: true,
+            parquet: false,
+            parquet_max_rows: 100_000,
+            retention_days: 7,
+        })
+        .unwrap();
+        let readings = vec![Reading::new("node1", 12_000)
+            .with_metric("temperature", 21.5, Some("celsius"), MetricKind::Sensor)
+            .with_metric("packets_received", 3.0, None, MetricKind::Counter)];
+        // 2025-01-31 23:59:59, then just after midnight
+        for received_secs in [1_738_367_999, 1_738_368_001] {
+            let event = SinkEvent::Telemetry {
+                readings: readings.clone(),
+                received_secs,
+            };
+            sink.write(&event).await.unwrap();
+        }
+        sink.shutdown().await.unwrap();
+
+        let csv = fs::read_to_string(directory.join("telemetry-2025-01-31.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                CSV_HEADER,
+                "2025-01-31T23:59:59Z,node1,12000,temperature,21.5,celsius,",
+                "2025-01-31T23:59:59Z,node1,12000,packets_received,3,,",
+            ]
+        );
+        assert!(directory.join("telemetry-2025-02-01.csv").exists());
+        assert!(!directory.join("telemetry-2025-01-01.csv").exists());
+        assert!(directory.join("notes.txt").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_file() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let path = std::env::temp_dir().join(format!("wk7-file-sink-{}.parquet", std::process::id()));
+        let readings = vec![Reading::new("node1", 12_000)
+            .with_metric("temperature", 21.5, Some("celsius"), MetricKind::Sensor)
+            .with_metric("packets_received", 3.0, None, MetricKind::Counter)];
+        let rows: Vec<Row> = Row::from_readings(&readings, 1_738_324_805).collect();
+        parquet_file::write(&path, &rows).unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().num_columns(), 7);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn test_parquet_row_limit() {
+        let directory = std::env::temp_dir().join(format!("wk7-file-sink-limit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut sink = FileSink::new(FileSinkOptions {
+            directory: directory.clone(),
+            csv: false,
+            parquet: true,
+            parquet_max_rows: 3,
+            retention_days: 0,
+        })
+        .unwrap();
+        let readings = vec![Reading::new("node1", 12_000)
+            .with_metric("temperature", 21.5, Some("celsius"), MetricKind::Sensor)
+            .with_metric("humidity", 40.0, Some("percent"), MetricKind::Sensor)];
+        // Four packets in the same second: two files of four rows, none left
+        for _ in 0..4 {
+            let event = SinkEvent::Telemetry {
+                readings: readings.clone(),
+                received_secs: 1_738_324_805,
+            };
+            sink.write(&event).await.unwrap();
+        }
+        assert!(sink.files.lock().unwrap().pending.is_empty());
+        sink.shutdown().await.unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["telemetry-2025-01-31T120005-1.parquet", "telemetry-2025-01-31T120005.parquet"]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Firmware (defmt) log lines from probe-rs
+//!
+//! probe-rs prints decoded defmt logs as
+//! `[WARN ] CRC FAIL: ... (wk5_gateway_firmware src/main.rs:674)`, optionally
+//! preceded by a timestamp. Those lines are parsed into level, message and
+//! source location and re-emitted through `tracing` with the `firmware`
+//! target, so they reach the same log pipeline as the gateway's own events.
+
+use serde_json::json;
+use tracing::Level;
+
+/// Target used for re-emitted firmware events (e.g. `RUST_LOG=firmware=warn`)
+pub const TARGET: &str = "firmware";
+
+/// One decoded defmt log line
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirmwareLog {
+    pub level: Level,
+    /// Firmware timestamp, if defmt timestamps are enabled
+    pub timestamp: Option<String>,
+    pub message: String,
+    /// Firmware crate/module that logged the line
+    pub module: Option<String>,
+    /// Source location, e.g. "src/main.rs:674"
+    pub location: Option<String>,
+}
+
+impl FirmwareLog {
+    /// Parse a probe-rs defmt line, `None` for anything else
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end().trim_end_matches("\\n").trim_end();
+        let open = line.find('[')?;
+        let close = open + line[open..].find(']')?;
+
+        // Only a timestamp may precede the level
+        let prefix = line[..open].trim();
+        if prefix.contains(char::is_whitespace) {
+            return None;
+        }
+        let level = match line[open + 1..close].trim() {
+            "TRACE" => Level::TRACE,
+            "DEBUG" => Level::DEBUG,
+            "INFO" => Level::INFO,
+            "WARN" => Level::WARN,
+            "ERROR" => Level::ERROR,
+            _ => return None,
+        };
+
+        let body = line[close + 1..].trim();
+        let (message, module, location) = split_location(body);
+
+        Some(Self {
+            level,
+            timestamp: (!prefix.is_empty()).then(|| prefix.to_string()),
+            message: message.to_string(),
+            module: module.map(str::to_string),
+            location: location.map(str::to_string),
+        })
+    }
+
+    /// Re-emit through `tracing` with the `firmware` target
+    pub fn emit(&self) {
+        // Missing parts are left out of the event rather than logged empty
+        let module = self.module.as_deref();
+        let location = self.location.as_deref();
+        let timestamp = self.timestamp.as_deref();
+        let message = self.message.as_str();
+
+        match self.level {
+            Level::ERROR => tracing::error!(target: TARGET, module, location, timestamp, "{}", message),
+            Level::WARN => tracing::warn!(target: TARGET, module, location, timestamp, "{}", message),
+            Level::INFO => tracing::info!(target: TARGET, module, location, timestamp, "{}", message),
+            Level::DEBUG => tracing::debug!(target: TARGET, module, location, timestamp, "{}", message),
+            Level::TRACE => tracing::trace!(target: TARGET, module, location, timestamp, "{}", message),
+        }
+    }
+
+    /// JSON payload for the MQTT log topic
+    pub fn to_json(&self) -> String {
+        json!({
+            "level": self.level.as_str(),
+            "message": self.message,
+            "module": self.module,
+            "location": self.location,
+            "timestamp": self.timestamp,
+        })
+        .to_string()
+    }
+}
+
+/// Split a trailing `(module file:line)` or `(file:line)` off the message
+fn split_location(body: &str) -> (&str, Option<&str>, Option<&str>) {
+    let Some(inner) = body.strip_suffix(')') else {
+        return (body, None, None);
+    };
+    let Some(open) = inner.rfind(" (") else {
+        return (body, None, None);
+    };
+    let suffix = &inner[open + 2..];
+
+    let (module, location) = match suffix.rsplit_once(' ') {
+        Some((module, location)) => (Some(module), location),
+        None => (None, suffix),
+    };
+    let is_location = location
+        .rsplit_once(':')
+        .is_some_and(|(file, line)| !file.is_empty() && line.parse::<u32>().is_ok());
+    if !is_location || module.is_some_and(|m| m.contains(' ')) {
+        return (body, None, None);
+    }
+
+    (inner[..open].trim_end(), module, Some(location))
+}
+
+/// MQTT topic for forwarded firmware logs
+pub fn mqtt_topic(topic_prefix: &str) -> String {
+    format!("{}/gateway/log", topic_prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defmt_line() {
+        let log = FirmwareLog::parse("[WARN ] CRC FAIL: computed 0x1234 (wk5_gateway_firmware src/main.rs:674)\n").unwrap();
+        assert_eq!(log.level, Level::WARN);
+        assert_eq!(log.message, "CRC FAIL: computed 0x1234");
+        assert_eq!(log.module.as_deref(), Some("wk5_gateway_firmware"));
+        assert_eq!(log.location.as_deref(), Some("src/main.rs:674"));
+        assert_eq!(log.timestamp, None);
+
+        let log = FirmwareLog::parse("12.345678 [INFO] UART errors cleared (link 3)").unwrap();
+        assert_eq!(log.level, Level::INFO);
+        assert_eq!(log.timestamp.as_deref(), Some("12.345678"));
+        // Parenthesized text that isn't a location stays in the message
+        assert_eq!(log.message, "UART errors cleared (link 3)");
+        assert_eq!(log.location, None);
+
+        assert!(FirmwareLog::parse("      Finished release [optimized] target(s)").is_none());
+        assert!(FirmwareLog::parse("probe-rs: flashing done").is_none());
+    }
+}
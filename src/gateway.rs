@@ -0,0 +1,947 @@
+//! Gateway pipeline
+//!
+//! Architecture: source → parser → channel → processor → sink queues → sinks
+//!
+//! The source is probe-rs running the gateway firmware, a captured log
+//! (`replay`) or synthetic packets (`simulate`). [`run`] is the entry point
+//! used by the service binary; programs embedding the gateway call it with
+//! their own [`SinkRegistry`] to add sink types without touching `main.rs`.
+
+use crate::alerts::{self, AlertEngine, AlertEvent};
+use crate::availability::{Availability, StatusChange};
+use crate::config::{Config, GatewayConfig};
+use crate::derived::Deriver;
+use crate::fanout::{Fanout, SinkEvent};
+use crate::firmware_log::{self, FirmwareLog};
+use crate::health::Health;
+use crate::link::LinkAnalytics;
+use crate::metrics::Metrics;
+use crate::reboot::{RebootDetector, RebootEvent};
+use crate::sequence::{SeqEvent, SequenceTracker};
+use crate::sink::{self, Connection, SinkContext, SinkRegistry};
+use crate::telemetry::{MetricKind, Quality, Reading};
+use crate::validation::Validator;
+use crate::week5::{self, Mismatch};
+use crate::{aggregation, calibration, http, influxdb, logging, mqtt, reload};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, info, warn};
+
+/// Firmware log lines waiting for MQTT forwarding
+const FIRMWARE_LOG_CAPACITY: usize = 64;
+
+/// Alert notifications waiting for MQTT/webhook delivery
+const ALERT_CAPACITY: usize = 64;
+
+/// How often nodes are checked for going offline
+const AVAILABILITY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Extract JSON from probe-rs log line
+///
+/// Example input: `[INFO] JSON sent via VCP: {"ts":12000,...}\n`
+/// Returns: `{"ts":12000,...}`
+fn extract_json_from_log_line(line: &str) -> Option<String> {
+    // Look for the JSON marker in the log
+    if let Some(start_idx) = line.find("JSON sent via VCP: ") {
+        let json_start = start_idx + "JSON sent via VCP: ".len();
+        let json_str = &line[json_start..];
+
+        // Remove the escaped \n and defmt source location suffix
+        // Format: {...}\n (wk5_gateway_firmware src/main.rs:573)
+        let without_location = json_str
+            .split(" (")  // Split on defmt source location
+            .next()       // Take everything before the location
+            .unwrap_or(json_str)
+            .trim();
+
+        // Remove both escaped \\n and actual \n characters
+        let json_clean = without_location
+            .trim_end_matches("\\n")
+            .trim_end_matches('\n')
+            .trim();
+
+        Some(json_clean.to_string())
+    } else {
+        None
+    }
+}
+
+/// Accept a bare JSON packet line (NDJSON captures used by `replay`)
+fn extract_raw_json_line(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('{') && trimmed.ends_with('}') {
+        Some(trimmed.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parse probe-rs output and send telemetry packets to channel
+///
+/// Works on anything line-oriented: the live probe-rs stdout or a replayed
+/// capture file. `pace` inserts a delay after every forwarded packet.
+/// Firmware defmt log lines are re-emitted via `tracing` and, if `logs` is
+/// given, queued for MQTT forwarding.
+async fn parse_probe_rs_output<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    tx: mpsc::Sender<Vec<Reading>>,
+    logs: Option<&mpsc::Sender<FirmwareLog>>,
+    pace: Option<Duration>,
+    metrics: &Metrics,
+) -> Result<()> {
+    let mut line_buf = String::new();
+    let mut reported = HashSet::new();
+
+    info!("Starting probe-rs output parser");
+
+    loop {
+        line_buf.clear();
+
+        match reader.read_line(&mut line_buf).await {
+            Ok(0) => {
+                warn!("probe-rs output ended (EOF)");
+                break;
+            }
+            Ok(_) => {
+                // Try to extract JSON from this line
+                let json = extract_json_from_log_line(&line_buf)
+                    .or_else(|| extract_raw_json_line(&line_buf));
+                if let Some(json_str) = json {
+                    let Some(readings) = ingest_json(&json_str, metrics, &mut reported) else {
+                        continue;
+                    };
+
+                    if let Err(e) = tx.send(readings).await {
+                        error!(error = %e, "Failed to send packet to channel");
+                        break;
+                    }
+                    metrics
+                        .channel_depth
+                        .set((tx.max_capacity() - tx.capacity()) as i64);
+
+                    if let Some(delay) = pace {
+                        tokio::time::sleep(delay).await;
+                    }
+                } else if let Some(log) = FirmwareLog::parse(&line_buf) {
+                    log.emit();
+                    if let Some(logs) = logs {
+                        // Never hold up telemetry for log forwarding
+                        let _ = logs.try_send(log);
+                    }
+                }
+            }
+            Err(e) => {
+                error!(error = %e, "Error reading from probe-rs stdout");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a Week 5 JSON packet into readings, updating the parser metrics
+///
+/// Schema mismatches are counted every time but only logged as warnings the
+/// first time each one is seen (tracked in `reported`). Returns `None` if
+/// the packet held no usable telemetry.
+fn ingest_json(json_str: &str, metrics: &Metrics, reported: &mut HashSet<Mismatch>) -> Option<Vec<Reading>> {
+    let decoded = match week5::decode(json_str) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            metrics.json_parse_failures.inc();
+            warn!(error = %e, json = %json_str, "Failed to parse JSON");
+            return None;
+        }
+    };
+
+    for mismatch in &decoded.mismatches {
+        metrics
+            .schema_mismatches
+            .with_label_values(&[mismatch.kind.as_str()])
+            .inc();
+        if reported.insert(mismatch.clone()) {
+            warn!(schema_version = decoded.version, mismatch = %mismatch, "Telemetry schema mismatch");
+        } else {
+            debug!(schema_version = decoded.version, mismatch = %mismatch, "Telemetry schema mismatch");
+        }
+    }
+
+    if decoded.readings.is_empty() {
+        metrics.json_parse_failures.inc();
+        warn!(json = %json_str, "Packet contained no telemetry");
+        return None;
+    }
+
+    metrics.packets_parsed.inc();
+    let (rx, err) = decoded.counters();
+    if let Some(rx) = rx {
+        metrics.firmware_rx.set(rx as i64);
+    }
+    if let Some(err) = err {
+        metrics.firmware_err.set(err as i64);
+    }
+
+    let link = decoded.readings.iter().find_map(|r| r.link);
+    info!(
+        schema_version = decoded.version,
+        timestamp_ms = decoded.readings[0].timestamp_ms,
+        readings = decoded.readings.len(),
+        rssi_dbm = link.map(|l| l.rssi_dbm),
+        "Telemetry packet received"
+    );
+
+    Some(decoded.readings)
+}
+
+/// Process telemetry readings and hand them to the sinks
+///
+/// Settings are read from `config` per packet, so reloaded values take
+/// effect on the next packet. Publishing happens in the sink workers (see
+/// [`sink`]), each behind its own queue.
+async fn process_telemetry(
+    mut rx: mpsc::Receiver<Vec<Reading>>,
+    fanout: Fanout,
+    mut config: watch::Receiver<Config>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    alerts: mpsc::Sender<AlertEvent>,
+) {
+    info!("Starting telemetry processor");
+
+    let mut sequences = SequenceTracker::new(week5::SEQ_MODULUS);
+    let mut reboots = RebootDetector::new();
+    let mut validator = Validator::new();
+    let mut deriver = Deriver::new();
+    let mut link_analytics = LinkAnalytics::new();
+    let mut alert_engine = AlertEngine::new(&config.borrow().alerts.rules);
+    let mut availability = Availability::new();
+    let mut availability_check = tokio::time::interval(AVAILABILITY_CHECK_INTERVAL);
+    availability_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        let mut readings = tokio::select! {
+            packet = rx.recv() => match packet {
+                Some(readings) => readings,
+                None => break,
+            },
+            _ = availability_check.tick() => {
+                check_availability(&mut availability, &fanout, &config, &metrics).await;
+                continue;
+            }
+        };
+        health.record_packet();
+        metrics.channel_depth.set(rx.len() as i64);
+        let received_secs = aggregation::unix_now_secs();
+
+        track_sequences(&mut sequences, &mut readings, &metrics);
+        if readings.is_empty() {
+            continue;
+        }
+
+        let events = reboots.observe(&mut readings);
+        if !events.is_empty() {
+            report_reboots(&events, &metrics);
+            fanout.send(SinkEvent::Reboots(events)).await;
+        }
+
+        let (validation, derived, link) = {
+            let config = config.borrow();
+            calibration::calibrate(&mut readings, &config.calibration);
+            (config.validation.clone(), config.derived.clone(), config.link.clone())
+        };
+        validator.check(&mut readings, &validation);
+        report_quality(&readings, &metrics);
+        deriver.derive(&mut readings, &derived);
+        link_analytics.analyze(&mut readings, &link);
+
+        // Pick up reloaded settings
+        if config.has_changed().unwrap_or(false) {
+            alert_engine.set_rules(&config.borrow_and_update().alerts.rules);
+        }
+        let (alerts_enabled, availability_enabled) = {
+            let config = config.borrow();
+            (config.alerts.enabled, config.availability.enabled)
+        };
+
+        if alerts_enabled {
+            evaluate_alerts(&mut alert_engine, &readings, &alerts, &metrics);
+        }
+
+        if availability_enabled {
+            let changes = availability.seen(&readings, Instant::now());
+            if !changes.is_empty() {
+                report_availability(&changes, &metrics);
+                fanout.send(SinkEvent::Status(changes)).await;
+            }
+        }
+
+        for reading in &readings {
+            info!(
+                node_id = %reading.node_id,
+                timestamp_ms = reading.timestamp_ms,
+                rssi_dbm = reading.link.map(|l| l.rssi_dbm),
+                snr_db = reading.link.map(|l| l.snr_db),
+                metrics = %reading,
+                "Processing telemetry reading"
+            );
+        }
+
+        fanout
+            .send(SinkEvent::Telemetry {
+                readings,
+                received_secs,
+            })
+            .await;
+    }
+
+    // Let the sinks drain their queues and stop
+    fanout.close();
+    info!("Telemetry processor stopped");
+}
+
+/// Check sequence numbers for loss, duplicates and resets
+///
+/// Adds a `packet_loss_pct` metric to each sequenced reading and drops
+/// readings that are duplicate retransmissions. Readings the gateway made
+/// itself (no sequence number) are kept.
+fn track_sequences(sequences: &mut SequenceTracker, readings: &mut Vec<Reading>, metrics: &Metrics) {
+    readings.retain_mut(|reading| {
+        let Some(seq) = reading.seq else {
+            return true;
+        };
+        let node = reading.node_id.as_str();
+
+        match sequences.observe(node, u64::from(seq)) {
+            SeqEvent::Duplicate => {
+                metrics.lora_duplicates.with_label_values(&[node]).inc();
+                info!(node_id = %node, seq, "Dropping duplicate LoRa packet");
+                return false;
+            }
+            SeqEvent::Gap { missed } => {
+                metrics.lora_packets_missed.with_label_values(&[node]).inc_by(missed);
+                warn!(node_id = %node, seq, missed, "LoRa packets lost");
+            }
+            SeqEvent::Reset => {
+                metrics.lora_sequence_resets.with_label_values(&[node]).inc();
+                warn!(node_id = %node, seq, "LoRa sequence reset (node rebooted?)");
+            }
+            SeqEvent::First | SeqEvent::InOrder => {}
+        }
+
+        if let Some(loss) = sequences.loss_ratio(node) {
+            metrics.lora_packet_loss_ratio.with_label_values(&[node]).set(loss);
+            reading.add_metric("packet_loss_pct", loss * 100.0, Some("percent"), MetricKind::Link);
+        }
+        true
+    });
+}
+
+/// Count and log values the validation stage didn't pass as good
+fn report_quality(readings: &[Reading], metrics: &Metrics) {
+    for reading in readings {
+        for metric in &reading.metrics {
+            let Some(quality) = metric.quality.filter(|q| *q != Quality::Good) else {
+                continue;
+            };
+            metrics
+                .values_flagged
+                .with_label_values(&[metric.name.as_str(), quality.as_str()])
+                .inc();
+            warn!(
+                node_id = %reading.node_id,
+                metric = %metric.name,
+                value = metric.value,
+                quality = quality.as_str(),
+                "Implausible telemetry value"
+            );
+        }
+    }
+}
+
+/// Run the alert rules and queue notifications for state changes
+fn evaluate_alerts(
+    engine: &mut AlertEngine,
+    readings: &[Reading],
+    alerts: &mpsc::Sender<AlertEvent>,
+    metrics: &Metrics,
+) {
+    for event in engine.evaluate(readings) {
+        match event.state {
+            alerts::AlertState::Firing => warn!(
+                rule = %event.rule,
+                node_id = %event.node,
+                value = event.value,
+                threshold = event.threshold,
+                severity = %event.severity,
+                "Alert firing: {}", event.condition
+            ),
+            _ => info!(rule = %event.rule, node_id = %event.node, value = event.value, "Alert resolved"),
+        }
+        if alerts.try_send(event).is_err() {
+            metrics.alert_notification_failures.with_label_values(&["queue"]).inc();
+            warn!("Alert notification queue full, dropping notification");
+        }
+    }
+
+    for (rule, firing) in engine.firing() {
+        metrics.alerts_firing.with_label_values(&[rule]).set(firing as i64);
+    }
+}
+
+/// Count and log firmware reboots (the sinks publish them)
+fn report_reboots(events: &[RebootEvent], metrics: &Metrics) {
+    for event in events {
+        metrics.firmware_reboots.with_label_values(&[event.node.as_str()]).inc();
+        warn!(
+            node_id = %event.node,
+            previous_uptime_ms = event.previous_uptime_ms,
+            timestamp_ms = event.timestamp_ms,
+            "Firmware reboot detected"
+        );
+    }
+}
+
+/// Mark nodes offline that went quiet for longer than their timeout
+async fn check_availability(
+    availability: &mut Availability,
+    fanout: &Fanout,
+    config: &watch::Receiver<Config>,
+    metrics: &Metrics,
+) {
+    let settings = config.borrow().availability.clone();
+    if !settings.enabled {
+        return;
+    }
+
+    let now = Instant::now();
+    for (node, age) in availability.last_seen(now) {
+        metrics.node_last_seen_seconds.with_label_values(&[node]).set(age.as_secs_f64());
+    }
+    let changes = availability.check(now, |node| settings.timeout(node));
+    if !changes.is_empty() {
+        report_availability(&changes, metrics);
+        fanout.send(SinkEvent::Status(changes)).await;
+    }
+}
+
+/// Count and log node online/offline transitions (the sinks publish them)
+fn report_availability(changes: &[StatusChange], metrics: &Metrics) {
+    for change in changes {
+        let node = change.node.as_str();
+        metrics.node_online.with_label_values(&[node]).set(i64::from(change.online));
+        if change.online {
+            info!(node_id = %node, "Node online");
+        } else {
+            warn!(node_id = %node, "Node offline: no packets within timeout");
+        }
+    }
+}
+
+/// Generate synthetic telemetry packets (for `simulate`)
+///
+/// Values drift slowly around plausible indoor readings so dashboards and
+/// downstream stages see realistic, changing data.
+async fn simulate_telemetry(
+    tx: mpsc::Sender<Vec<Reading>>,
+    interval: Duration,
+    count: Option<u64>,
+    metrics: Arc<Metrics>,
+) {
+    info!(interval_ms = interval.as_millis() as u64, count = ?count, "Starting telemetry simulator");
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut reported = HashSet::new();
+    let mut n: u64 = 0;
+
+    while count.is_none_or(|c| n < c) {
+        ticker.tick().await;
+
+        let phase = n as f32 / 30.0;
+        // Same shape and precision as the firmware's format_json_telemetry
+        let json = format!(
+            r#"{{"v":{},"ts":{},"id":"N2","n1":{{"seq":{},"t":{:.1},"h":{:.1},"g":{}}},"n2":{{"t":{:.1},"h":{:.1}}},"sig":{{"rssi":{},"snr":{}}},"sts":{{"rx":{},"err":{}}}}}"#,
+            week5::SCHEMA_VERSION,
+            n * interval.as_millis() as u64,
+            n % week5::SEQ_MODULUS,
+            22.0 + 2.0 * phase.sin(),
+            45.0 + 5.0 * phase.cos(),
+            85_000 + (n % 50) * 100,
+            24.0 + phase.sin(),
+            40.0 + 3.0 * phase.cos(),
+            -60 - (n % 10) as i64,
+            9 - (n % 4) as i64,
+            n + 1,
+            n / 100,
+        );
+
+        let Some(readings) = ingest_json(&json, &metrics, &mut reported) else {
+            break;
+        };
+        if tx.send(readings).await.is_err() {
+            break;
+        }
+        n += 1;
+    }
+
+    info!(packets = n, "Telemetry simulator finished");
+}
+
+/// Spawn probe-rs running the gateway firmware, with stdout piped
+///
+/// The process is killed when its handle is dropped, so aborting the task
+/// that owns it stops the board cleanly.
+fn spawn_probe_rs(gateway: &GatewayConfig) -> Result<tokio::process::Child> {
+    info!(
+        probe = gateway.probe_id,
+        chip = gateway.chip,
+        firmware = gateway.firmware_path,
+        "Spawning probe-rs subprocess"
+    );
+
+    tokio::process::Command::new("probe-rs")
+        .args([
+            "run",
+            "--probe",
+            &gateway.probe_id,
+            "--chip",
+            &gateway.chip,
+            &gateway.firmware_path,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit()) // Pass through stderr for errors
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn probe-rs process")
+}
+
+/// Forward probe-rs output to the channel until probe-rs exits
+///
+/// With `gateway.restart_on_exit` set, probe-rs is restarted after
+/// `restart_delay_secs` (this reflashes and resets the board).
+async fn supervise_probe_rs(
+    mut child: tokio::process::Child,
+    gateway: GatewayConfig,
+    tx: mpsc::Sender<Vec<Reading>>,
+    logs: mpsc::Sender<FirmwareLog>,
+    metrics: Arc<Metrics>,
+) {
+    let delay = Duration::from_secs(gateway.restart_delay_secs);
+
+    loop {
+        match child.stdout.take() {
+            Some(stdout) => {
+                let reader = BufReader::new(stdout);
+                if let Err(e) = parse_probe_rs_output(reader, tx.clone(), Some(&logs), None, &metrics).await {
+                    error!(error = %e, "Parser task failed");
+                }
+            }
+            None => error!("Failed to capture probe-rs stdout"),
+        }
+
+        let status = child.wait().await;
+        if !gateway.restart_on_exit || tx.is_closed() {
+            warn!(status = ?status, "probe-rs exited");
+            break;
+        }
+
+        warn!(status = ?status, delay_secs = gateway.restart_delay_secs, "probe-rs exited, restarting");
+        child = loop {
+            tokio::time::sleep(delay).await;
+            match spawn_probe_rs(&gateway) {
+                Ok(child) => break child,
+                Err(e) => error!(error = %format!("{:#}", e), "Failed to restart probe-rs, retrying"),
+            }
+        };
+        metrics.probe_rs_restarts.inc();
+    }
+}
+
+/// Publish firmware log lines to MQTT per `logging.firmware_mqtt_level`
+async fn forward_firmware_logs(
+    mut rx: mpsc::Receiver<FirmwareLog>,
+    mqtt_client: Arc<mqtt::MqttClient>,
+    config: watch::Receiver<Config>,
+) {
+    while let Some(log) = rx.recv().await {
+        let (min_level, topic) = {
+            let config = config.borrow();
+            (
+                config.logging.firmware_mqtt_level.parse::<tracing::Level>().ok(),
+                firmware_log::mqtt_topic(&config.mqtt.topic_prefix),
+            )
+        };
+        // "off" doesn't parse as a level
+        if min_level.is_none_or(|min| log.level > min) {
+            continue;
+        }
+
+        if let Err(e) = mqtt_client
+            .publish(&topic, &log.to_json(), mqtt_client.qos(), false)
+            .await
+        {
+            warn!(error = %e, "Failed to forward firmware log to MQTT");
+        }
+    }
+}
+
+/// Deliver alert notifications to MQTT (retained) and the webhook
+///
+/// Runs apart from the processor so a slow webhook doesn't hold up
+/// telemetry. Without an MQTT connection only the webhook is notified.
+async fn notify_alerts(
+    mut rx: mpsc::Receiver<AlertEvent>,
+    mqtt_client: Option<Arc<mqtt::MqttClient>>,
+    config: watch::Receiver<Config>,
+    metrics: Arc<Metrics>,
+) {
+    let http = reqwest::Client::new();
+
+    while let Some(event) = rx.recv().await {
+        let (topic, webhook_url, timeout) = {
+            let config = config.borrow();
+            (
+                event.mqtt_topic(&config.mqtt.topic_prefix),
+                config.alerts.webhook_url.clone(),
+                Duration::from_secs(config.alerts.webhook_timeout_secs),
+            )
+        };
+        let payload = event.to_json();
+
+        if let Some(mqtt_client) = &mqtt_client {
+            if let Err(e) = mqtt_client.publish(&topic, &payload, mqtt_client.qos(), true).await {
+                metrics.alert_notification_failures.with_label_values(&["mqtt"]).inc();
+                warn!(error = %e, alert = %event, "Failed to publish alert to MQTT");
+            }
+        }
+
+        if let Some(url) = webhook_url {
+            let result = http
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(payload)
+                .timeout(timeout)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                metrics.alert_notification_failures.with_label_values(&["webhook"]).inc();
+                warn!(error = %e, alert = %event, "Failed to send alert webhook");
+            }
+        }
+    }
+}
+
+/// Where the pipeline gets its telemetry from
+pub enum Source {
+    /// Live probe-rs subprocess running the gateway firmware
+    ProbeRs,
+    /// Captured probe-rs log or NDJSON file
+    Replay { file: PathBuf, interval: Duration },
+    /// Synthetic packets
+    Simulate { interval: Duration, count: Option<u64> },
+}
+
+/// Create the MQTT client from config
+pub async fn connect_mqtt(config: &Config) -> Result<mqtt::MqttClient> {
+    let credentials = config
+        .mqtt
+        .username
+        .as_deref()
+        .map(|user| (user, config.mqtt.password.as_deref().unwrap_or("")));
+
+    mqtt::MqttClient::new(
+        &config.mqtt.broker_url,
+        &config.mqtt.client_id,
+        config.mqtt.qos,
+        credentials,
+    )
+    .await
+    .context("Failed to create MQTT client")
+}
+
+/// Create the InfluxDB client from config and verify the connection
+pub async fn connect_influxdb(config: &Config) -> Result<influxdb::InfluxDbClient> {
+    let influxdb_client = influxdb::InfluxDbClient::new(
+        &config.influxdb.url,
+        &config.influxdb.org,
+        &config.influxdb.bucket,
+        &config.influxdb.token,
+    )
+    .context("Failed to create InfluxDB client")?;
+
+    // Test InfluxDB connection
+    influxdb_client
+        .health_check()
+        .await
+        .context("InfluxDB health check failed")?;
+    info!("InfluxDB connection verified");
+
+    Ok(influxdb_client)
+}
+
+/// Re-read the configuration on every reload trigger and publish it
+///
+/// Only runtime-reloadable settings are applied; anything else is logged as
+/// needing a restart. Invalid configs are rejected and the current one kept.
+async fn reload_config(
+    reload: Reload,
+    mut triggers: reload::ReloadTriggers,
+    config_tx: watch::Sender<Config>,
+    registry: Arc<SinkRegistry>,
+) {
+    while let Some(reason) = triggers.next().await {
+        info!(?reason, "Reloading configuration");
+
+        let loaded = (reload.load)().and_then(|new| {
+            registry.validate(&new.sinks).context("Invalid sinks")?;
+            Ok(new)
+        });
+        let new = match loaded {
+            Ok(new) => new,
+            Err(e) => {
+                error!(error = %format!("{:#}", e), "Config reload failed, keeping current configuration");
+                continue;
+            }
+        };
+
+        let current = config_tx.borrow().clone();
+        let needs_restart = current.restart_required_changes(&new);
+        if !needs_restart.is_empty() {
+            warn!(fields = ?needs_restart, "Changed settings require a restart to take effect");
+        }
+
+        reload.log.apply_config(&new.logging);
+        config_tx.send_replace(current.reloadable_from(&new));
+        info!("Configuration reloaded");
+    }
+}
+
+/// Hot reload settings for [`run`]
+pub struct Reload {
+    /// Config file to watch
+    pub path: PathBuf,
+    /// Re-read and validate the configuration, e.g. with command-line overrides
+    pub load: Box<dyn Fn() -> Result<Config> + Send + Sync>,
+    /// Receives `[logging]` changes
+    pub log: logging::LogHandle,
+}
+
+/// Run the full pipeline: source → parser → channel → processor → sinks
+///
+/// `[[sinks]]` are built from `registry`; pass [`SinkRegistry::builtin`],
+/// with any extra sink types registered, and `reload` to follow config
+/// changes. Returns once the source ends or on Ctrl+C.
+pub async fn run(config: Config, registry: SinkRegistry, source: Source, reload: Option<Reload>) -> Result<()> {
+    registry.validate(&config.sinks).context("Invalid sinks")?;
+    let registry = Arc::new(registry);
+
+    // Only connect to what the enabled sinks write through
+    let connections = registry.connections(&config.sinks);
+
+    let mqtt_client = if connections.contains(&Connection::Mqtt) {
+        let mqtt_client = connect_mqtt(&config).await?;
+
+        // Publish test message (Phase 2.2)
+        mqtt_client
+            .publish_test_message(&config.mqtt.topic_prefix)
+            .await
+            .context("Failed to publish test message")?;
+        info!("Test message published successfully");
+        Some(Arc::new(mqtt_client))
+    } else {
+        info!("No enabled sink uses MQTT, not connecting to the broker");
+        None
+    };
+
+    let influxdb_client = if connections.contains(&Connection::InfluxDb) {
+        let influxdb_client = connect_influxdb(&config).await?;
+
+        // Install long-term rollup tasks (non-fatal: raw telemetry still flows)
+        if let Some(downsampling) = config.influxdb.downsampling.as_ref().filter(|d| d.enabled) {
+            if let Err(e) = influxdb_client.provision_downsampling(downsampling).await {
+                error!(error = %e, "Failed to provision InfluxDB downsampling tasks");
+            }
+        }
+        Some(Arc::new(influxdb_client))
+    } else {
+        info!("No enabled sink uses InfluxDB, not connecting");
+        None
+    };
+
+    // Metrics, health state and HTTP endpoints
+    let metrics = Arc::new(Metrics::new().context("Failed to create metrics")?);
+    metrics.channel_capacity.set(config.gateway.channel_capacity as i64);
+    let health = Arc::new(Health::new(
+        mqtt_client.as_ref().map(|client| client.connection_flag()),
+        influxdb_client.as_ref().map(|client| client.reachability_flag()),
+        Duration::from_secs(config.http.ready_timeout_secs),
+    ));
+    if config.http.enabled {
+        let state = http::HttpState {
+            metrics: metrics.clone(),
+            health: health.clone(),
+        };
+        let listen_addr = config.http.listen_addr.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(&listen_addr, state).await {
+                error!(error = %format!("{:#}", e), "HTTP server stopped");
+            }
+        });
+    }
+
+    // Live configuration, updated by the reload task
+    let (config_tx, config_rx) = watch::channel(config.clone());
+
+    // Sink workers from [[sinks]], each draining its own queue
+    let sink_context = SinkContext {
+        mqtt: mqtt_client.clone(),
+        influxdb: influxdb_client,
+        config: config_rx.clone(),
+        metrics: metrics.clone(),
+    };
+    let mut fanout = Fanout::new(metrics.clone());
+    let mut sink_handles = Vec::new();
+    for (name, sink) in registry.build(&config.sinks, &sink_context)? {
+        info!(sink = %name, "Starting sink");
+        let queue = fanout.add(&name, &config.queue(&name));
+        sink_handles.push(tokio::spawn(sink::run(name, sink, queue, metrics.clone())));
+    }
+
+    // Create channel for telemetry packets
+    let (tx, rx) = mpsc::channel::<Vec<Reading>>(config.gateway.channel_capacity);
+    let (log_tx, log_rx) = mpsc::channel::<FirmwareLog>(FIRMWARE_LOG_CAPACITY);
+
+    // Spawn the source task feeding the channel
+    let hardware = matches!(source, Source::ProbeRs);
+    let mut source_handle = match source {
+        Source::ProbeRs => {
+            let child = spawn_probe_rs(&config.gateway)?;
+            tokio::spawn(supervise_probe_rs(
+                child,
+                config.gateway.clone(),
+                tx,
+                log_tx,
+                metrics.clone(),
+            ))
+        }
+        Source::Replay { file, interval } => {
+            info!(file = %file.display(), "Replaying captured telemetry");
+            let input = tokio::fs::File::open(&file)
+                .await
+                .with_context(|| format!("Failed to open replay file: {}", file.display()))?;
+            let pace = (!interval.is_zero()).then_some(interval);
+            let metrics = metrics.clone();
+
+            tokio::spawn(async move {
+                let reader = BufReader::new(input);
+                if let Err(e) = parse_probe_rs_output(reader, tx, Some(&log_tx), pace, &metrics).await {
+                    error!(error = %e, "Parser task failed");
+                }
+            })
+        }
+        Source::Simulate { interval, count } => {
+            tokio::spawn(simulate_telemetry(tx, interval, count, metrics.clone()))
+        }
+    };
+
+    // Reload the configuration when the file changes or on SIGHUP
+    if let Some(reload) = reload {
+        match reload::ReloadTriggers::spawn(&reload.path) {
+            Ok(triggers) => {
+                tokio::spawn(reload_config(reload, triggers, config_tx, registry.clone()));
+            }
+            Err(e) => warn!(error = %format!("{:#}", e), "Config hot reload disabled"),
+        }
+    }
+
+    // Forward firmware logs to MQTT (dropped when over capacity)
+    match &mqtt_client {
+        Some(mqtt_client) => {
+            tokio::spawn(forward_firmware_logs(log_rx, mqtt_client.clone(), config_rx.clone()));
+        }
+        None if config.logging.firmware_mqtt_level != "off" => {
+            warn!("logging.firmware_mqtt_level is set but no enabled sink uses MQTT, firmware logs are not published");
+        }
+        None => {}
+    }
+
+    // Deliver alert notifications
+    if mqtt_client.is_none() && config.alerts.enabled && config.alerts.webhook_url.is_none() {
+        warn!("No enabled sink uses MQTT and no alerts.webhook_url is set, alerts are only logged");
+    }
+    let (alert_tx, alert_rx) = mpsc::channel::<AlertEvent>(ALERT_CAPACITY);
+    let notifier_handle = tokio::spawn(notify_alerts(
+        alert_rx,
+        mqtt_client.clone(),
+        config_rx.clone(),
+        metrics.clone(),
+    ));
+
+    // Spawn processor task
+    let processor_handle = tokio::spawn(process_telemetry(rx, fanout, config_rx, metrics, health, alert_tx));
+
+    // Wait for Ctrl+C or the source to run dry
+    info!("Service running. Press Ctrl+C to stop.");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            info!("Received Ctrl+C, shutting down gracefully");
+            if hardware {
+                info!("Killing probe-rs subprocess");
+            }
+            // Dropping the source task kills probe-rs (kill_on_drop)
+            source_handle.abort();
+            let _ = (&mut source_handle).await;
+        }
+        _ = &mut source_handle => {
+            if hardware {
+                warn!("Parser task ended unexpectedly");
+            } else {
+                info!("Telemetry source finished");
+            }
+        }
+    }
+
+    // Wait for processor to drain the channel, then for the sinks and
+    // pending notifications
+    processor_handle.await.ok();
+    for handle in sink_handles {
+        handle.await.ok();
+    }
+    notifier_handle.await.ok();
+
+    info!("Week 7 Gateway Service stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_json_from_log_line() {
+        let line = r#"[INFO] JSON sent via VCP: {"ts":12000,"id":"N2"}\n"#;
+        let result = extract_json_from_log_line(line);
+        assert_eq!(result, Some(r#"{"ts":12000,"id":"N2"}"#.to_string()));
+    }
+
+    #[test]
+    fn test_extract_raw_json_line() {
+        let line = "{\"ts\":12000,\"id\":\"N2\"}\n";
+        assert_eq!(extract_raw_json_line(line), Some(r#"{"ts":12000,"id":"N2"}"#.to_string()));
+        assert_eq!(extract_raw_json_line("[INFO] Some other log message"), None);
+    }
+
+    #[test]
+    fn test_extract_json_no_match() {
+        let line = "[INFO] Some other log message";
+        let result = extract_json_from_log_line(line);
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Liveness and readiness state for `/healthz` and `/readyz`
+//!
+//! The gateway is ready when:
+//! - telemetry reached the processor within the last `ready_timeout_secs`
+//!   (counted from startup until the first packet arrives)
+//! - the MQTT client is connected to the broker
+//! - the last InfluxDB health check or write succeeded
+//!
+//! Connections no enabled sink uses aren't opened and don't count.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Live health state shared between the pipeline and the HTTP server
+pub struct Health {
+    started: Instant,
+    /// Milliseconds after `started` when the last packet was processed
+    last_packet_ms: AtomicU64,
+    packet_seen: AtomicBool,
+    mqtt_connected: Option<Arc<AtomicBool>>,
+    influxdb_reachable: Option<Arc<AtomicBool>>,
+    source_timeout: Duration,
+}
+
+/// Result of a readiness check, serialized as the `/readyz` body
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub source_ok: bool,
+    /// `None` if the gateway doesn't use MQTT
+    pub mqtt_connected: Option<bool>,
+    /// `None` if the gateway doesn't use InfluxDB
+    pub influxdb_reachable: Option<bool>,
+    /// Seconds since the last packet (or since startup if none yet)
+    pub last_packet_age_secs: f64,
+    pub packets_seen: bool,
+}
+
+impl Health {
+    /// Create health state from the clients' connection flags
+    ///
+    /// # Arguments
+    /// * `mqtt_connected` - Flag from [`crate::mqtt::MqttClient::connection_flag`], if connected
+    /// * `influxdb_reachable` - Flag from [`crate::influxdb::InfluxDbClient::reachability_flag`], if connected
+    /// * `source_timeout` - Max time without packets before the gateway is not ready
+    pub fn new(
+        mqtt_connected: Option<Arc<AtomicBool>>,
+        influxdb_reachable: Option<Arc<AtomicBool>>,
+        source_timeout: Duration,
+    ) -> Self {
+        Self {
+            started: Instant::now(),
+            last_packet_ms: AtomicU64::new(0),
+            packet_seen: AtomicBool::new(false),
+            mqtt_connected,
+            influxdb_reachable,
+            source_timeout,
+        }
+    }
+
+    /// Record that a telemetry packet was processed just now
+    pub fn record_packet(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_packet_ms.store(elapsed, Ordering::Relaxed);
+        self.packet_seen.store(true, Ordering::Relaxed);
+    }
+
+    /// Evaluate readiness now
+    pub fn readiness(&self) -> Readiness {
+        self.readiness_at(self.started.elapsed())
+    }
+
+    fn readiness_at(&self, now: Duration) -> Readiness {
+        let last = Duration::from_millis(self.last_packet_ms.load(Ordering::Relaxed));
+        let age = now.saturating_sub(last);
+        let source_ok = age <= self.source_timeout;
+        let mqtt_connected = self.mqtt_connected.as_ref().map(|flag| flag.load(Ordering::Relaxed));
+        let influxdb_reachable = self.influxdb_reachable.as_ref().map(|flag| flag.load(Ordering::Relaxed));
+
+        Readiness {
+            ready: source_ok && mqtt_connected != Some(false) && influxdb_reachable != Some(false),
+            source_ok,
+            mqtt_connected,
+            influxdb_reachable,
+            last_packet_age_secs: age.as_secs_f64(),
+            packets_seen: self.packet_seen.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let mqtt = Arc::new(AtomicBool::new(true));
+        let influx = Arc::new(AtomicBool::new(true));
+        let health = Health::new(Some(mqtt.clone()), Some(influx), Duration::from_secs(30));
+
+        // Startup grace period counts as activity
+        assert!(health.readiness_at(Duration::from_secs(10)).ready);
+        assert!(!health.readiness_at(Duration::from_secs(31)).source_ok);
+
+        health.record_packet();
+        let r = health.readiness();
+        assert!(r.ready);
+        assert!(r.packets_seen);
+
+        mqtt.store(false, Ordering::Relaxed);
+        let r = health.readiness();
+        assert!(!r.ready);
+        assert_eq!(r.mqtt_connected, Some(false));
+        assert_eq!(r.influxdb_reachable, Some(true));
+
+        // Without an MQTT sink the broker doesn't matter
+        let health = Health::new(None, Some(Arc::new(AtomicBool::new(true))), Duration::from_secs(30));
+        let r = health.readiness();
+        assert!(r.ready);
+        assert_eq!(r.mqtt_connected, None);
+    }
+}
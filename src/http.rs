@@ -0,0 +1,76 @@
+//! Embedded HTTP server
+//!
+//! Endpoints:
+//! - `/metrics`: Prometheus metrics (see [`crate::metrics`])
+//! - `/healthz`: process is alive (always 200 while serving)
+//! - `/readyz`: 200 when the pipeline is healthy, 503 otherwise, with a
+//!   JSON body describing each check (see [`crate::health`])
+
+use crate::health::Health;
+use crate::metrics::Metrics;
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Shared state for the HTTP handlers
+#[derive(Clone)]
+pub struct HttpState {
+    pub metrics: Arc<Metrics>,
+    pub health: Arc<Health>,
+}
+
+/// Build the router with all endpoints
+pub fn router(state: HttpState) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .with_state(state)
+}
+
+/// Serve the endpoints on `listen_addr` until the task is dropped
+pub async fn serve(listen_addr: &str, state: HttpState) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen_addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP server to {}", listen_addr))?;
+
+    info!(addr = listen_addr, "HTTP server listening (/metrics, /healthz, /readyz)");
+
+    axum::serve(listener, router(state))
+        .await
+        .context("HTTP server failed")
+}
+
+async fn metrics_handler(State(state): State<HttpState>) -> impl IntoResponse {
+    match state.metrics.render() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            body,
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error = %e, "Failed to render metrics");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+async fn readyz_handler(State(state): State<HttpState>) -> impl IntoResponse {
+    let readiness = state.health.readiness();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
@@ -5,17 +5,32 @@
 //! - Writes telemetry data in line protocol format
 //! - Handles authentication with API tokens
 //! - Supports batched writes for efficiency
+//! - Provisions downsampling tasks for long-term storage
 
+use crate::aggregation::Window;
+use crate::config::DownsamplingConfig;
 use anyhow::{Context, Result};
+use influxdb2::api::buckets::ListBucketsRequest;
+use influxdb2::api::organization::ListOrganizationRequest;
+use influxdb2::api::task::{CreateTaskRequest, ListTasksRequest};
+use influxdb2::models::retention_rule::Type as RetentionType;
+use influxdb2::models::{DataPoint, PostBucketRequest, RetentionRule, TaskStatusType};
 use influxdb2::Client;
-use influxdb2::models::DataPoint;
-use tracing::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Aggregates written for every downsampling window
+const ROLLUP_FUNCTIONS: [&str; 3] = ["mean", "min", "max"];
 
 /// InfluxDB client for writing telemetry
 pub struct InfluxDbClient {
     client: Client,
+    org: String,
     bucket: String,
     url: String,
+    /// Outcome of the last health check or write
+    reachable: Arc<AtomicBool>,
 }
 
 impl InfluxDbClient {
@@ -33,11 +48,23 @@ impl InfluxDbClient {
 
         Ok(Self {
             client,
+            org: org.to_string(),
             bucket: bucket.to_string(),
             url: url.to_string(),
+            reachable: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Shared flag tracking whether InfluxDB answered the last request
+    pub fn reachability_flag(&self) -> Arc<AtomicBool> {
+        self.reachable.clone()
+    }
+
+    /// Whether the last health check or write succeeded
+    pub fn is_reachable(&self) -> bool {
+        self.reachable.load(Ordering::Relaxed)
+    }
+
     /// Test connection to InfluxDB with health check
     pub async fn health_check(&self) -> Result<()> {
         info!("Testing InfluxDB connection...");
@@ -47,9 +74,11 @@ impl InfluxDbClient {
         let health_url = format!("{}/health", self.url);
 
         let response = reqwest::get(&health_url).await
+            .inspect_err(|_| self.reachable.store(false, Ordering::Relaxed))
             .context("Failed to connect to InfluxDB health endpoint")?;
 
         let status = response.status();
+        self.reachable.store(status.is_success(), Ordering::Relaxed);
         if status.is_success() {
             info!(status = %status, "InfluxDB health check passed");
             Ok(())
@@ -84,10 +113,12 @@ impl InfluxDbClient {
         let point = point.build()?;
 
         // Write to InfluxDB
-        self.client
+        let result = self
+            .client
             .write(&self.bucket, futures::stream::iter(vec![point]))
-            .await
-            .context("Failed to write data point to InfluxDB")?;
+            .await;
+        self.reachable.store(result.is_ok(), Ordering::Relaxed);
+        result.context("Failed to write data point to InfluxDB")?;
 
         info!(
             measurement = measurement,
@@ -99,30 +130,277 @@ impl InfluxDbClient {
         Ok(())
     }
 
-    /// Write telemetry sensor value to InfluxDB
+    /// Write a batch of points in one request
+    pub async fn write_points(&self, points: Vec<DataPoint>) -> Result<()> {
+        let count = points.len();
+        let result = self.client.write(&self.bucket, futures::stream::iter(points)).await;
+        self.reachable.store(result.is_ok(), Ordering::Relaxed);
+        result.context("Failed to write points to InfluxDB")?;
+
+        info!(points = count, "Wrote points to InfluxDB");
+        Ok(())
+    }
+
+    /// Write a node availability point: `node_status,node=<node> online=1|0`
+    pub async fn write_node_status(&self, node_id: &str, online: bool) -> Result<()> {
+        self.write_point("node_status", "online", if online { 1.0 } else { 0.0 }, vec![("node", node_id)])
+            .await
+    }
+
+    /// Write a reboot event: `reboot,node=<node> previous_uptime_ms=<ms>`
+    pub async fn write_reboot(&self, node_id: &str, previous_uptime_ms: u64) -> Result<()> {
+        self.write_point("reboot", "previous_uptime_ms", previous_uptime_ms as f64, vec![("node", node_id)])
+            .await
+    }
+
+    /// Write a closed aggregation window, one point per node and metric
     ///
-    /// This is a convenience wrapper around write_point for sensor data
+    /// Each point goes to the metric's measurement, tagged with `node`,
+    /// `unit` and `window` (e.g. "60s"), with fields `mean`, `min`, `max`,
+    /// `last` and `count`, timestamped at the window start. With
+    /// `mean_as_value` the mean is also written as `value`, so dashboards and
+    /// downsampling tasks built on raw points keep working without them.
+    pub async fn write_aggregates(&self, window: &Window, mean_as_value: bool) -> Result<()> {
+        let timestamp_ns = i64::try_from(window.start_secs)
+            .ok()
+            .and_then(|s| s.checked_mul(1_000_000_000))
+            .context("Window start out of range")?;
+        let window_tag = format!("{}s", window.length_secs);
+
+        let mut points = Vec::with_capacity(window.series.len());
+        for series in &window.series {
+            let aggregate = &series.aggregate;
+            let mut point = DataPoint::builder(series.metric.as_str())
+                .tag("node", series.node.as_str())
+                .tag("window", window_tag.as_str())
+                .field("mean", aggregate.mean())
+                .field("min", aggregate.min)
+                .field("max", aggregate.max)
+                .field("last", aggregate.last)
+                .field("count", aggregate.count as i64)
+                .timestamp(timestamp_ns);
+            if let Some(unit) = &series.unit {
+                point = point.tag("unit", unit.as_str());
+            }
+            if mean_as_value {
+                point = point.field("value", aggregate.mean());
+            }
+            points.push(point.build()?);
+        }
+
+        let result = self.client.write(&self.bucket, futures::stream::iter(points)).await;
+        self.reachable.store(result.is_ok(), Ordering::Relaxed);
+        result.context("Failed to write aggregates to InfluxDB")?;
+
+        info!(
+            window_start = window.start_secs,
+            window = %window_tag,
+            series = window.series.len(),
+            "Wrote aggregates to InfluxDB"
+        );
+        Ok(())
+    }
+
+    /// Install the downsampling tasks described by `config`
     ///
-    /// # Arguments
-    /// * `sensor_type` - Type of sensor (e.g., "temperature", "humidity")
-    /// * `value` - Sensor reading
-    /// * `node_id` - Node identifier (e.g., "node1", "node2")
-    /// * `unit` - Optional unit tag (e.g., "celsius", "percent")
-    pub async fn write_sensor(
-        &self,
-        sensor_type: &str,
-        value: f64,
-        node_id: &str,
-        unit: Option<&str>,
-    ) -> Result<()> {
-        let mut tags = vec![("node", node_id)];
+    /// Creates the long-term bucket if it doesn't exist, then creates one
+    /// task per window. Existing tasks with the same name are left alone
+    /// when their Flux script is unchanged and replaced otherwise, so this
+    /// is safe to call on every startup.
+    pub async fn provision_downsampling(&self, config: &DownsamplingConfig) -> Result<()> {
+        info!(
+            bucket = %config.bucket,
+            windows = ?config.windows,
+            "Provisioning InfluxDB downsampling tasks"
+        );
+
+        self.ensure_bucket(&config.bucket, config.retention_days).await?;
+
+        for window in &config.windows {
+            let name = format!("{}-{}", config.task_prefix, window);
+            let flux = downsampling_task_flux(&name, window, &self.bucket, &config.bucket, &self.org);
+
+            let existing = self
+                .client
+                .list_tasks(ListTasksRequest {
+                    name: Some(name.clone()),
+                    org: Some(self.org.clone()),
+                    ..Default::default()
+                })
+                .await
+                .with_context(|| format!("Failed to list InfluxDB tasks named {}", name))?;
+
+            if let Some(task) = existing.tasks.iter().find(|t| t.name == name) {
+                if task.flux.trim() == flux.trim() {
+                    info!(task = %name, "Downsampling task already up to date");
+                    continue;
+                }
+
+                // No update endpoint in the client, so replace the task
+                warn!(task = %name, "Downsampling task changed, replacing it");
+                self.client
+                    .delete_task(&task.id)
+                    .await
+                    .with_context(|| format!("Failed to delete InfluxDB task {}", name))?;
+            }
+
+            let mut request = CreateTaskRequest::new(flux);
+            request.org = Some(self.org.clone());
+            request.status = Some(TaskStatusType::Active);
+            request.description = Some(format!(
+                "{} mean/min/max rollup of {} into {}",
+                window, self.bucket, config.bucket
+            ));
+
+            self.client
+                .create_task(request)
+                .await
+                .with_context(|| format!("Failed to create InfluxDB task {}", name))?;
+
+            info!(task = %name, "Created downsampling task");
+        }
 
-        if let Some(u) = unit {
-            tags.push(("unit", u));
+        Ok(())
+    }
+
+    /// Create a bucket with the given retention if it doesn't exist yet
+    async fn ensure_bucket(&self, name: &str, retention_days: u32) -> Result<()> {
+        let buckets = self
+            .client
+            .list_buckets(Some(ListBucketsRequest {
+                name: Some(name.to_string()),
+                org: Some(self.org.clone()),
+                ..Default::default()
+            }))
+            .await
+            .context("Failed to list InfluxDB buckets")?;
+
+        if buckets.buckets.iter().any(|b| b.name == name) {
+            return Ok(());
         }
 
-        self.write_point(sensor_type, "value", value, tags).await
+        let orgs = self
+            .client
+            .list_organizations(ListOrganizationRequest {
+                org: Some(self.org.clone()),
+                ..Default::default()
+            })
+            .await
+            .context("Failed to look up InfluxDB organization")?;
+
+        let org_id = orgs
+            .orgs
+            .iter()
+            .find(|o| o.name == self.org)
+            .and_then(|o| o.id.clone())
+            .with_context(|| format!("InfluxDB organization not found: {}", self.org))?;
+
+        let retention_secs = i32::try_from(u64::from(retention_days) * 86_400)
+            .context("Downsampling retention is too long")?;
+
+        let mut request = PostBucketRequest::new(org_id, name.to_string());
+        request.retention_rules = vec![RetentionRule::new(RetentionType::Expire, retention_secs)];
+
+        self.client
+            .create_bucket(Some(request))
+            .await
+            .with_context(|| format!("Failed to create InfluxDB bucket {}", name))?;
+
+        info!(bucket = name, retention_days = retention_days, "Created long-term bucket");
+        Ok(())
+    }
+}
+
+/// Build a telemetry point: `<sensor_type>,node=<node>[,unit=..][,quality=..] value=<value>`
+///
+/// Points carry their own timestamp since they may sit in a batch for a
+/// while before being written.
+///
+/// # Arguments
+/// * `sensor_type` - Type of sensor (e.g., "temperature", "humidity")
+/// * `value` - Sensor reading
+/// * `node_id` - Node identifier (e.g., "node1", "node2")
+/// * `unit` - Optional unit tag (e.g., "celsius", "percent")
+/// * `quality` - Optional quality tag ("good", "suspect", "bad")
+/// * `timestamp_ns` - Receive time in nanoseconds since the Unix epoch
+pub fn sensor_point(
+    sensor_type: &str,
+    value: f64,
+    node_id: &str,
+    unit: Option<&str>,
+    quality: Option<&str>,
+    timestamp_ns: i64,
+) -> Result<DataPoint> {
+    let mut point = DataPoint::builder(sensor_type)
+        .tag("node", node_id)
+        .field("value", value)
+        .timestamp(timestamp_ns);
+    if let Some(u) = unit {
+        point = point.tag("unit", u);
+    }
+    if let Some(q) = quality {
+        point = point.tag("quality", q);
+    }
+    Ok(point.build()?)
+}
+
+/// Build the Flux script for one downsampling task
+///
+/// Every run aggregates the last `window` of raw points into mean/min/max
+/// fields, tagged with the window so several rollups can share a bucket.
+pub fn downsampling_task_flux(
+    task_name: &str,
+    window: &str,
+    source_bucket: &str,
+    dest_bucket: &str,
+    org: &str,
+) -> String {
+    let task_name = flux_string(task_name);
+    let source_bucket = flux_string(source_bucket);
+    let dest_bucket = flux_string(dest_bucket);
+    let org = flux_string(org);
+
+    let mut flux = format!(
+        "option task = {{name: {task_name}, every: {window}}}\n\n\
+         data = from(bucket: {source_bucket})\n    \
+         |> range(start: -task.every)\n    \
+         |> filter(fn: (r) => r._field == \"value\")\n"
+    );
+
+    for func in ROLLUP_FUNCTIONS {
+        flux.push_str(&format!(
+            "\ndata\n    \
+             |> aggregateWindow(every: {window}, fn: {func}, createEmpty: false)\n    \
+             |> set(key: \"_field\", value: \"{func}\")\n    \
+             |> set(key: \"window\", value: \"{window}\")\n    \
+             |> to(bucket: {dest_bucket}, org: {org})\n"
+        ));
+    }
+
+    flux
+}
+
+/// Quote a value as a Flux string literal
+///
+/// Escapes backslashes, quotes, newlines and `${` so names taken from the
+/// config can't end the literal or start an interpolation.
+fn flux_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
     }
+    out.push('"');
+    out
 }
 
 #[cfg(test)]
@@ -141,4 +419,32 @@ mod tests {
         );
         assert!(client.is_ok());
     }
+
+    #[test]
+    fn test_downsampling_task_flux() {
+        let flux = downsampling_task_flux(
+            "wk7-downsample-1m",
+            "1m",
+            "telemetry",
+            "telemetry_longterm",
+            "my-org",
+        );
+
+        assert!(flux.starts_with("option task = {name: \"wk7-downsample-1m\", every: 1m}"));
+        assert!(flux.contains("from(bucket: \"telemetry\")"));
+        for func in ROLLUP_FUNCTIONS {
+            assert!(flux.contains(&format!("fn: {}, createEmpty: false", func)));
+        }
+        assert_eq!(flux.matches("to(bucket: \"telemetry_longterm\", org: \"my-org\")").count(), 3);
+    }
+
+    #[test]
+    fn test_flux_string_escaping() {
+        assert_eq!(flux_string("telemetry"), "\"telemetry\"");
+        assert_eq!(flux_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(flux_string("${x} $5"), "\"\\${x} $5\"");
+
+        let flux = downsampling_task_flux("t", "1h", "raw\")", "long", "org");
+        assert!(flux.contains("from(bucket: \"raw\\\")\")"));
+    }
 }
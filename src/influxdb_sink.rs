@@ -0,0 +1,193 @@
+//! InfluxDB sink (Phase 5)
+//!
+//! Writes one measurement per metric, optionally aggregated over windows
+//! (see [`crate::aggregation`]), plus `reboot` and `node_status` points.
+//! Raw points are batched per `influxdb.batch_size` and
+//! `influxdb.flush_interval_ms`, both re-read on every packet so a config
+//! reload applies them. Batches and open aggregation windows are written on
+//! [`TelemetrySink::flush`] once their time is up, and at shutdown.
+
+use crate::aggregation::{self, Aggregator, Window};
+use crate::config::{Config, SinkConfig};
+use crate::fanout::SinkEvent;
+use crate::influxdb::{self, InfluxDbClient};
+use crate::metrics::Metrics;
+use crate::sink::{self, SinkContext, TelemetrySink};
+use crate::telemetry::{Quality, Reading};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use influxdb2::models::DataPoint;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+pub struct InfluxDbSink {
+    client: Arc<InfluxDbClient>,
+    config: watch::Receiver<Config>,
+    metrics: Arc<Metrics>,
+    aggregator: Aggregator,
+    /// Raw points waiting for the next batch write
+    pending: Vec<DataPoint>,
+    last_write: Instant,
+}
+
+impl InfluxDbSink {
+    /// Factory for the `influxdb` sink type (takes no options)
+    pub fn from_config(config: &SinkConfig, ctx: &SinkContext) -> Result<Self> {
+        sink::no_options(config)?;
+        Ok(Self {
+            client: ctx.influxdb_client()?,
+            config: ctx.config.clone(),
+            metrics: ctx.metrics.clone(),
+            aggregator: Aggregator::new(),
+            pending: Vec::new(),
+            last_write: Instant::now(),
+        })
+    }
+
+    /// Queue readings, one measurement per metric, writing the batch when due
+    async fn write_telemetry(&mut self, readings: &[Reading]) -> Result<()> {
+        let timestamp_ns = unix_now_ns();
+        for reading in readings {
+            for metric in &reading.metrics {
+                self.pending.push(influxdb::sensor_point(
+                    &metric.name,
+                    metric.value,
+                    &reading.node_id,
+                    metric.unit.as_deref(),
+                    metric.quality.map(Quality::as_str),
+                    timestamp_ns,
+                )?);
+            }
+        }
+
+        let batch_size = self.config.borrow().influxdb.batch_size;
+        if self.pending.len() >= batch_size || self.batch_due() {
+            self.write_pending().await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the flush interval has passed since the last batch write
+    fn batch_due(&self) -> bool {
+        let interval = Duration::from_millis(self.config.borrow().influxdb.flush_interval_ms);
+        self.last_write.elapsed() >= interval
+    }
+
+    /// Write all queued raw points in one request
+    ///
+    /// The batch is dropped if the write fails, like a single packet was
+    /// before batching.
+    async fn write_pending(&mut self) -> Result<()> {
+        self.last_write = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let points = std::mem::take(&mut self.pending);
+        self.timed(self.client.write_points(points)).await
+    }
+
+    /// Write a closed aggregation window, if there is one
+    ///
+    /// Without raw points the mean also goes to the `value` field.
+    async fn write_aggregates(&self, window: Option<Window>, write_raw: bool) -> Result<()> {
+        let Some(window) = window else {
+            return Ok(());
+        };
+        self.timed(self.client.write_aggregates(&window, !write_raw)).await
+    }
+
+    /// Run a write, updating the write metrics
+    async fn timed(&self, write: impl std::future::Future<Output = Result<()>>) -> Result<()> {
+        let timer = self.metrics.influxdb_write_seconds.start_timer();
+        let result = write.await;
+        if result.is_ok() {
+            timer.observe_duration();
+            self.metrics.influxdb_writes.inc();
+        } else {
+            timer.stop_and_discard();
+            self.metrics.influxdb_write_failures.inc();
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TelemetrySink for InfluxDbSink {
+    async fn write(&mut self, event: &SinkEvent) -> Result<()> {
+        match event {
+            SinkEvent::Telemetry {
+                readings,
+                received_secs,
+            } => {
+                let aggregation = self.config.borrow().aggregation.clone();
+                if aggregation.enabled {
+                    let closed = self.aggregator.add(readings, *received_secs, aggregation.window_secs);
+                    self.write_aggregates(closed, aggregation.write_raw).await?;
+                }
+                if !aggregation.enabled || aggregation.write_raw {
+                    self.write_telemetry(readings)
+                        .await
+                        .context("Failed to write telemetry to InfluxDB")?;
+                }
+                Ok(())
+            }
+            SinkEvent::Reboots(events) => {
+                for event in events {
+                    self.client
+                        .write_reboot(&event.node, event.previous_uptime_ms)
+                        .await
+                        .with_context(|| format!("Failed to write reboot event for {} to InfluxDB", event.node))?;
+                }
+                Ok(())
+            }
+            SinkEvent::Status(changes) => {
+                for change in changes {
+                    self.client
+                        .write_node_status(&change.node, change.online)
+                        .await
+                        .with_context(|| format!("Failed to write node status for {} to InfluxDB", change.node))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Write the raw batch and close the aggregation window once their
+    /// time is up
+    async fn flush(&mut self) -> Result<()> {
+        let batch = if self.batch_due() {
+            self.write_pending().await.context("Failed to write telemetry to InfluxDB")
+        } else {
+            Ok(())
+        };
+
+        let aggregation = self.config.borrow().aggregation.clone();
+        let closed = if aggregation.enabled {
+            self.aggregator.flush(aggregation::unix_now_secs(), aggregation.window_secs)
+        } else {
+            self.aggregator.finish()
+        };
+        batch.and(self.write_aggregates(closed, aggregation.write_raw).await)
+    }
+
+    async fn health(&self) -> bool {
+        self.client.is_reachable()
+    }
+
+    /// Don't lose the pending batch or the partial window
+    async fn shutdown(&mut self) -> Result<()> {
+        let batch = self.write_pending().await.context("Failed to write telemetry to InfluxDB");
+        let write_raw = self.config.borrow().aggregation.write_raw;
+        let window = self.aggregator.finish();
+        batch.and(self.write_aggregates(window, write_raw).await)
+    }
+}
+
+/// Nanoseconds since the Unix epoch, the timestamp of raw points
+fn unix_now_ns() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
@@ -5,3 +5,27 @@
 pub mod config;
 pub mod mqtt;
 pub mod influxdb;
+pub mod logging;
+pub mod reload;
+pub mod metrics;
+pub mod health;
+pub mod http;
+pub mod firmware_log;
+pub mod telemetry;
+pub mod week5;
+pub mod sequence;
+pub mod validation;
+pub mod derived;
+pub mod calibration;
+pub mod alerts;
+pub mod availability;
+pub mod link;
+pub mod reboot;
+pub mod aggregation;
+pub mod deadband;
+pub mod fanout;
+pub mod sink;
+pub mod mqtt_sink;
+pub mod influxdb_sink;
+pub mod file_sink;
+pub mod gateway;
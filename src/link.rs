@@ -0,0 +1,216 @@
+//! LoRa link-quality analytics
+//!
+//! Adds metrics over a rolling window of the last `[link] window` packets:
+//! - readings with link metadata (Node 1): `rssi_avg`, `rssi_p10`,
+//!   `rssi_p90`, `snr_avg`, `snr_p10`, `snr_p90` and `link_margin`
+//! - readings with `packets_received`/`crc_errors` counters (`stats`):
+//!   `packet_error_rate`, from counter deltas so firmware resets don't
+//!   produce negative or huge values
+//!
+//! The link margin is the estimated signal power above the receiver
+//! sensitivity for the configured spreading factor and bandwidth (the
+//! firmware runs SF7 at 500 kHz). Signal power is derived from packet RSSI
+//! and SNR, so it stays meaningful below the noise floor where RSSI alone
+//! only shows noise.
+
+use crate::config::LinkConfig;
+use crate::telemetry::{MetricKind, Reading};
+use std::collections::{HashMap, VecDeque};
+
+/// Thermal noise density in dBm/Hz
+const THERMAL_NOISE_DBM_HZ: f64 = -174.0;
+
+/// Receiver noise figure assumed for the sensitivity estimate (SX127x)
+const NOISE_FIGURE_DB: f64 = 6.0;
+
+/// Minimum demodulation SNR for a spreading factor (SX127x datasheet)
+pub fn snr_floor_db(spreading_factor: u8) -> f64 {
+    -7.5 - 2.5 * (f64::from(spreading_factor) - 7.0)
+}
+
+/// Receiver sensitivity in dBm
+pub fn sensitivity_dbm(spreading_factor: u8, bandwidth_khz: f64) -> f64 {
+    THERMAL_NOISE_DBM_HZ + 10.0 * (bandwidth_khz * 1000.0).log10() + NOISE_FIGURE_DB + snr_floor_db(spreading_factor)
+}
+
+/// Link margin in dB: estimated signal power minus sensitivity
+///
+/// Packet RSSI is signal plus noise; `snr` splits it back up.
+pub fn link_margin_db(rssi_dbm: f64, snr_db: f64, spreading_factor: u8, bandwidth_khz: f64) -> f64 {
+    let signal_dbm = rssi_dbm + snr_db - 10.0 * (1.0 + 10f64.powf(snr_db / 10.0)).log10();
+    signal_dbm - sensitivity_dbm(spreading_factor, bandwidth_khz)
+}
+
+/// Nearest-rank percentile of unsorted values (`p` in 0-100)
+fn percentile(values: &VecDeque<f64>, p: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn mean(values: &VecDeque<f64>) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Fixed-size window of recent values
+fn push(window: &mut VecDeque<f64>, value: f64, size: usize) {
+    while window.len() >= size {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
+
+#[derive(Default)]
+struct SignalWindow {
+    rssi: VecDeque<f64>,
+    snr: VecDeque<f64>,
+}
+
+#[derive(Default)]
+struct CounterWindow {
+    /// Last cumulative (received, errors)
+    last: Option<(f64, f64)>,
+    received: VecDeque<f64>,
+    errors: VecDeque<f64>,
+}
+
+/// Rolling link statistics per node
+#[derive(Default)]
+pub struct LinkAnalytics {
+    signal: HashMap<String, SignalWindow>,
+    counters: HashMap<String, CounterWindow>,
+}
+
+impl LinkAnalytics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add link analytics metrics to the readings of one packet
+    pub fn analyze(&mut self, readings: &mut [Reading], config: &LinkConfig) {
+        if !config.enabled {
+            return;
+        }
+        let size = config.window;
+
+        for reading in readings.iter_mut() {
+            if let Some(link) = reading.link {
+                let window = self.signal.entry(reading.node_id.clone()).or_default();
+                push(&mut window.rssi, link.rssi_dbm, size);
+                push(&mut window.snr, link.snr_db, size);
+
+                let (rssi_avg, snr_avg) = (mean(&window.rssi), mean(&window.snr));
+                let margin = link_margin_db(rssi_avg, snr_avg, config.spreading_factor, config.bandwidth_khz);
+                let stats = [
+                    ("rssi_avg", rssi_avg, "dbm"),
+                    ("rssi_p10", percentile(&window.rssi, 10.0), "dbm"),
+                    ("rssi_p90", percentile(&window.rssi, 90.0), "dbm"),
+                    ("snr_avg", snr_avg, "db"),
+                    ("snr_p10", percentile(&window.snr, 10.0), "db"),
+                    ("snr_p90", percentile(&window.snr, 90.0), "db"),
+                    ("link_margin", margin, "db"),
+                ];
+                for (name, value, unit) in stats {
+                    reading.add_metric(name, round(value), Some(unit), MetricKind::Link);
+                }
+            }
+
+            let counter = |name| reading.metric(name).map(|m| m.value);
+            if let (Some(received), Some(errors)) = (counter("packets_received"), counter("crc_errors")) {
+                let window = self.counters.entry(reading.node_id.clone()).or_default();
+                if let Some((last_received, last_errors)) = window.last {
+                    push(&mut window.received, delta(last_received, received), size);
+                    push(&mut window.errors, delta(last_errors, errors), size);
+                }
+                window.last = Some((received, errors));
+
+                let total = window.received.iter().sum::<f64>() + window.errors.iter().sum::<f64>();
+                if total > 0.0 {
+                    let per = window.errors.iter().sum::<f64>() / total * 100.0;
+                    reading.add_metric("packet_error_rate", round(per), Some("percent"), MetricKind::Link);
+                }
+            }
+        }
+    }
+}
+
+/// Increase of a cumulative counter; after a reset it counts from zero
+fn delta(previous: f64, current: f64) -> f64 {
+    if current >= previous {
+        current - previous
+    } else {
+        current
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::LinkMetadata;
+
+    fn config() -> LinkConfig {
+        LinkConfig {
+            window: 4,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_link_margin() {
+        // Within ~1.5 dB of the SX1276 datasheet (SF7/125 kHz -123 dBm,
+        // SF12/125 kHz -136 dBm)
+        assert!((sensitivity_dbm(7, 125.0) - -124.5).abs() < 0.1);
+        assert!((sensitivity_dbm(12, 125.0) - -137.0).abs() < 0.1);
+
+        // Strong signal: RSSI is essentially all signal
+        let margin = link_margin_db(-60.0, 10.0, 7, 500.0);
+        assert!((margin - (-60.4 + 118.5)).abs() < 0.1);
+        // At the SNR floor there's no margin left, whatever RSSI shows
+        let noise = THERMAL_NOISE_DBM_HZ + 10.0 * 500e3f64.log10() + NOISE_FIGURE_DB;
+        let rssi = noise + 10.0 * (1.0 + 10f64.powf(-0.75)).log10();
+        assert!(link_margin_db(rssi, -7.5, 7, 500.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_link_analytics() {
+        let mut analytics = LinkAnalytics::new();
+        let packet = |rssi: f64, rx: f64, err: f64| {
+            vec![
+                Reading::new("node1", 0).with_link(LinkMetadata { rssi_dbm: rssi, snr_db: 9.0 }),
+                Reading::new("stats", 0)
+                    .with_metric("packets_received", rx, None, MetricKind::Counter)
+                    .with_metric("crc_errors", err, None, MetricKind::Counter),
+            ]
+        };
+
+        let mut readings = Vec::new();
+        let packets = [
+            (-70.0, 100.0, 0.0),
+            (-80.0, 108.0, 2.0),
+            // Firmware rebooted: counters start over
+            (-90.0, 3.0, 0.0),
+            (-100.0, 10.0, 1.0),
+            (-60.0, 18.0, 1.0),
+        ];
+        for (rssi, rx, err) in packets {
+            readings = packet(rssi, rx, err);
+            analytics.analyze(&mut readings, &config());
+        }
+
+        // Window of 4: -80, -90, -100, -60
+        let node1 = &readings[0];
+        assert_eq!(node1.metric("rssi_avg").unwrap().value, -82.5);
+        assert_eq!(node1.metric("rssi_p10").unwrap().value, -100.0);
+        assert_eq!(node1.metric("rssi_p90").unwrap().value, -60.0);
+        assert!(node1.metric("link_margin").is_some());
+
+        // Deltas (8,2), reset (3,0), (7,1), (8,0): 3 errors out of 29
+        let per = readings[1].metric("packet_error_rate").unwrap().value;
+        assert_eq!(per, round(3.0 / 29.0 * 100.0));
+    }
+}
@@ -0,0 +1,182 @@
+//! Tracing setup for the gateway
+//!
+//! Both the log filter and the output layer sit behind reload layers, so the
+//! level, format and log file from config.toml can be changed at runtime
+//! (see [`LogHandle::apply_config`]).
+//!
+//! Output formats:
+//! - `pretty`: human-readable lines (default)
+//! - `json`: one JSON object per event with the event fields (`node_id`,
+//!   `rssi_dbm`, ...) at the top level, for Loki/Vector ingestion
+
+use crate::config::{LogFileConfig, LogFormat, LogRotation, LoggingConfig};
+use anyhow::{Context, Result};
+use std::path::Path;
+use tracing::{info, warn};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+/// Subscriber below the output layer (registry + reloadable filter)
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+
+/// Output layer(s): stdout plus optional log file
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// Handle for changing the log filter and output of the running subscriber
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    output: reload::Handle<Output, Filtered>,
+    /// Filter was fixed by --log-level or RUST_LOG; config changes are ignored
+    pinned: bool,
+    /// Format fixed by --log-format; config changes are ignored
+    cli_format: Option<LogFormat>,
+    current: std::sync::Mutex<String>,
+    current_output: std::sync::Mutex<(LogFormat, Option<LogFileConfig>)>,
+}
+
+/// Initialize the global tracing subscriber
+///
+/// `--log-level` wins over RUST_LOG, which wins over `logging.level` from
+/// config.toml. The config level isn't known yet at this point, so we start
+/// at "info" and [`LogHandle::apply_config`] switches once it's loaded.
+/// Likewise, output starts on stdout in `cli_format` (or pretty) and the
+/// config's format and log file are applied once it's loaded.
+pub fn init(cli_level: Option<&str>, cli_format: Option<LogFormat>) -> Result<LogHandle> {
+    let (filter, level, pinned) = match cli_level {
+        Some(level) => (
+            EnvFilter::try_new(level).with_context(|| format!("Invalid log level: {}", level))?,
+            level.to_string(),
+            true,
+        ),
+        None => match EnvFilter::try_from_default_env() {
+            Ok(filter) => {
+                let level = filter.to_string();
+                (filter, level, true)
+            }
+            Err(_) => (EnvFilter::new("info"), "info".to_string(), false),
+        },
+    };
+
+    let format = cli_format.unwrap_or_default();
+    let (filter_layer, filter_handle) = reload::Layer::new(filter);
+    let (output_layer, output_handle) = reload::Layer::new(output_layer(format, None)?);
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(output_layer)
+        .init();
+
+    Ok(LogHandle {
+        filter: filter_handle,
+        output: output_handle,
+        pinned,
+        cli_format,
+        current: std::sync::Mutex::new(level),
+        current_output: std::sync::Mutex::new((format, None)),
+    })
+}
+
+/// Build the stdout layer and, if configured, the log file layer
+fn output_layer(format: LogFormat, file: Option<&LogFileConfig>) -> Result<Output> {
+    let stdout = fmt_layer(format, std::io::stdout, true);
+    match file {
+        None => Ok(stdout),
+        Some(file) => {
+            let appender = file_appender(file)?;
+            Ok(stdout.and_then(fmt_layer(format, appender, false)).boxed())
+        }
+    }
+}
+
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> Output
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_thread_ids(true)
+        .with_writer(writer);
+    match format {
+        // Keep the target so `firmware` lines stand out from gateway logs
+        LogFormat::Pretty => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).boxed(),
+    }
+}
+
+fn file_appender(file: &LogFileConfig) -> Result<RollingFileAppender> {
+    let path = Path::new(&file.path);
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let name = path
+        .file_name()
+        .with_context(|| format!("Log file path has no file name: {}", file.path))?;
+
+    let rotation = match file.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(name.to_string_lossy())
+        .max_log_files(file.max_files)
+        .build(dir)
+        .with_context(|| format!("Failed to open log file {}", file.path))
+}
+
+impl LogHandle {
+    /// Switch to the level, format and log file from config.toml, except
+    /// for settings pinned on the command line (or RUST_LOG)
+    pub fn apply_config(&self, config: &LoggingConfig) {
+        self.apply_output(config);
+
+        if self.pinned {
+            return;
+        }
+
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        if *current == config.level {
+            return;
+        }
+
+        match EnvFilter::try_new(&config.level) {
+            Ok(filter) => match self.filter.reload(filter) {
+                Ok(()) => {
+                    info!(from = %current, to = %config.level, "Log level changed");
+                    *current = config.level.clone();
+                }
+                Err(e) => warn!(error = %e, "Failed to change log level"),
+            },
+            Err(e) => warn!(level = %config.level, error = %e, "Invalid log level, keeping current"),
+        }
+    }
+
+    fn apply_output(&self, config: &LoggingConfig) {
+        let wanted = (self.cli_format.unwrap_or(config.format), config.file.clone());
+
+        let mut current = self.current_output.lock().unwrap_or_else(|e| e.into_inner());
+        if *current == wanted {
+            return;
+        }
+
+        match output_layer(wanted.0, wanted.1.as_ref()) {
+            Ok(layer) => match self.output.reload(layer) {
+                Ok(()) => {
+                    info!(
+                        format = ?wanted.0,
+                        file = wanted.1.as_ref().map(|f| f.path.as_str()),
+                        "Log output changed"
+                    );
+                    *current = wanted;
+                }
+                Err(e) => warn!(error = %e, "Failed to change log output"),
+            },
+            Err(e) => warn!(error = %e, "Invalid log output, keeping current"),
+        }
+    }
+}
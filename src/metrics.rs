@@ -0,0 +1,220 @@
+//! Prometheus metrics for the gateway process
+//!
+//! All counters and gauges live in one [`Metrics`] value shared (via `Arc`)
+//! between the parser, the processor and the HTTP server, which renders them
+//! in the Prometheus text format on `/metrics`.
+
+use anyhow::Result;
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+/// Gateway metrics
+pub struct Metrics {
+    registry: Registry,
+    /// Telemetry packets parsed from probe-rs output
+    pub packets_parsed: IntCounter,
+    /// Lines with a JSON marker that failed to parse
+    pub json_parse_failures: IntCounter,
+    /// Schema deviations in decoded packets, by kind (see [`crate::week5::MismatchKind`])
+    pub schema_mismatches: IntCounterVec,
+    /// Packets published to MQTT
+    pub mqtt_publishes: IntCounter,
+    /// Packets that failed to publish to MQTT
+    pub mqtt_publish_failures: IntCounter,
+    /// Values withheld from MQTT by the deadband
+    pub mqtt_publishes_suppressed: IntCounter,
+    /// Packets written to InfluxDB
+    pub influxdb_writes: IntCounter,
+    /// Packets that failed to write to InfluxDB
+    pub influxdb_write_failures: IntCounter,
+    /// Time to write one packet to InfluxDB
+    pub influxdb_write_seconds: Histogram,
+    /// Packets waiting in the parser → processor channel
+    pub channel_depth: IntGauge,
+    /// Capacity of the parser → processor channel
+    pub channel_capacity: IntGauge,
+    /// Times probe-rs was restarted after exiting
+    pub probe_rs_restarts: IntCounter,
+    /// Latest firmware packets-received counter (`sts.rx`)
+    pub firmware_rx: IntGauge,
+    /// Latest firmware CRC error counter (`sts.err`)
+    pub firmware_err: IntGauge,
+    /// Packets lost on air, per node (from sequence gaps)
+    pub lora_packets_missed: IntCounterVec,
+    /// Duplicate retransmissions dropped, per node
+    pub lora_duplicates: IntCounterVec,
+    /// Sequence number resets (node reboots), per node
+    pub lora_sequence_resets: IntCounterVec,
+    /// Rolling packet-loss ratio, per node
+    pub lora_packet_loss_ratio: GaugeVec,
+    /// Values flagged by the validation stage, by metric and quality
+    pub values_flagged: IntCounterVec,
+    /// Nodes an alert rule is currently firing for, by rule
+    pub alerts_firing: IntGaugeVec,
+    /// Alert notifications that failed to send, by channel (mqtt, webhook)
+    pub alert_notification_failures: IntCounterVec,
+    /// 1 if the node sent a packet within its offline timeout, by node
+    pub node_online: IntGaugeVec,
+    /// Seconds since the last packet, by node
+    pub node_last_seen_seconds: GaugeVec,
+    /// Firmware reboots detected from timestamp/counter resets, by node
+    pub firmware_reboots: IntCounterVec,
+    /// Events waiting in each sink's queue, by sink
+    pub sink_queue_depth: IntGaugeVec,
+    /// Events dropped because a sink's queue was full, by sink
+    pub sink_queue_dropped: IntCounterVec,
+    /// 1 if the sink reports it can deliver, by sink
+    pub sink_healthy: IntGaugeVec,
+}
+
+impl Metrics {
+    /// Create and register all metrics
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        let counter = |name: &str, help: &str| -> Result<IntCounter> {
+            let c = IntCounter::new(name, help)?;
+            registry.register(Box::new(c.clone()))?;
+            Ok(c)
+        };
+        let gauge = |name: &str, help: &str| -> Result<IntGauge> {
+            let g = IntGauge::new(name, help)?;
+            registry.register(Box::new(g.clone()))?;
+            Ok(g)
+        };
+
+        let influxdb_write_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "wk7_influxdb_write_duration_seconds",
+                "Time to write one batch of telemetry points to InfluxDB",
+            )
+            .buckets(vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]),
+        )?;
+        registry.register(Box::new(influxdb_write_seconds.clone()))?;
+
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| -> Result<IntCounterVec> {
+            let c = IntCounterVec::new(Opts::new(name, help), labels)?;
+            registry.register(Box::new(c.clone()))?;
+            Ok(c)
+        };
+
+        let lora_packet_loss_ratio = GaugeVec::new(
+            Opts::new("wk7_lora_packet_loss_ratio", "Fraction of recent LoRa packets lost, by node"),
+            &["node"],
+        )?;
+        registry.register(Box::new(lora_packet_loss_ratio.clone()))?;
+
+        let alerts_firing = IntGaugeVec::new(
+            Opts::new("wk7_alerts_firing", "Nodes an alert rule is currently firing for, by rule"),
+            &["rule"],
+        )?;
+        registry.register(Box::new(alerts_firing.clone()))?;
+
+        let node_online = IntGaugeVec::new(
+            Opts::new("wk7_node_online", "1 if the node sent a packet within its offline timeout"),
+            &["node"],
+        )?;
+        registry.register(Box::new(node_online.clone()))?;
+
+        let node_last_seen_seconds = GaugeVec::new(
+            Opts::new("wk7_node_last_seen_seconds", "Seconds since the last packet from the node"),
+            &["node"],
+        )?;
+        registry.register(Box::new(node_last_seen_seconds.clone()))?;
+
+        let sink_queue_depth = IntGaugeVec::new(
+            Opts::new("wk7_sink_queue_depth", "Events waiting in the sink's queue"),
+            &["sink"],
+        )?;
+        registry.register(Box::new(sink_queue_depth.clone()))?;
+
+        let sink_healthy = IntGaugeVec::new(
+            Opts::new("wk7_sink_healthy", "1 if the sink reports it can deliver"),
+            &["sink"],
+        )?;
+        registry.register(Box::new(sink_healthy.clone()))?;
+
+        Ok(Self {
+            packets_parsed: counter("wk7_packets_parsed_total", "Telemetry packets parsed from probe-rs output")?,
+            json_parse_failures: counter("wk7_json_parse_failures_total", "Telemetry lines that failed to parse as JSON")?,
+            schema_mismatches: counter_vec(
+                "wk7_schema_mismatches_total",
+                "Telemetry fields that didn't match the packet schema",
+                &["kind"],
+            )?,
+            mqtt_publishes: counter("wk7_mqtt_publishes_total", "Telemetry packets published to MQTT")?,
+            mqtt_publish_failures: counter("wk7_mqtt_publish_failures_total", "Telemetry packets that failed to publish to MQTT")?,
+            mqtt_publishes_suppressed: counter(
+                "wk7_mqtt_publishes_suppressed_total",
+                "Metric values not published to MQTT because they were within the deadband",
+            )?,
+            influxdb_writes: counter("wk7_influxdb_writes_total", "Telemetry packets written to InfluxDB")?,
+            influxdb_write_failures: counter("wk7_influxdb_write_failures_total", "Telemetry packets that failed to write to InfluxDB")?,
+            influxdb_write_seconds,
+            channel_depth: gauge("wk7_channel_depth", "Packets waiting between parser and processor")?,
+            channel_capacity: gauge("wk7_channel_capacity", "Capacity of the parser to processor channel")?,
+            probe_rs_restarts: counter("wk7_probe_rs_restarts_total", "Times probe-rs was restarted after exiting")?,
+            firmware_rx: gauge("wk7_firmware_packets_received", "Latest firmware packets-received counter (sts.rx)")?,
+            firmware_err: gauge("wk7_firmware_crc_errors", "Latest firmware CRC error counter (sts.err)")?,
+            lora_packets_missed: counter_vec("wk7_lora_packets_missed_total", "LoRa packets lost on air (sequence gaps)", &["node"])?,
+            lora_duplicates: counter_vec("wk7_lora_duplicates_total", "Duplicate LoRa retransmissions dropped", &["node"])?,
+            lora_sequence_resets: counter_vec("wk7_lora_sequence_resets_total", "LoRa sequence number resets (node reboots)", &["node"])?,
+            lora_packet_loss_ratio,
+            values_flagged: counter_vec(
+                "wk7_values_flagged_total",
+                "Telemetry values flagged suspect or bad by validation",
+                &["metric", "quality"],
+            )?,
+            alerts_firing,
+            alert_notification_failures: counter_vec(
+                "wk7_alert_notification_failures_total",
+                "Alert notifications that failed to send",
+                &["channel"],
+            )?,
+            node_online,
+            node_last_seen_seconds,
+            firmware_reboots: counter_vec(
+                "wk7_firmware_reboots_total",
+                "Firmware reboots detected from timestamp or counter resets",
+                &["node"],
+            )?,
+            sink_queue_depth,
+            sink_queue_dropped: counter_vec(
+                "wk7_sink_queue_dropped_total",
+                "Events dropped because the sink's queue was full",
+                &["sink"],
+            )?,
+            sink_healthy,
+            registry,
+        })
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render() {
+        let metrics = Metrics::new().unwrap();
+        metrics.packets_parsed.inc();
+        metrics.firmware_rx.set(42);
+        metrics.influxdb_write_seconds.observe(0.02);
+        metrics.schema_mismatches.with_label_values(&["missing_field"]).inc();
+
+        let text = metrics.render().unwrap();
+        assert!(text.contains("wk7_packets_parsed_total 1"));
+        assert!(text.contains("wk7_firmware_packets_received 42"));
+        assert!(text.contains("wk7_influxdb_write_duration_seconds_count 1"));
+        assert!(text.contains(r#"wk7_schema_mismatches_total{kind="missing_field"} 1"#));
+    }
+}
@@ -7,7 +7,9 @@
 //! - Supports QoS levels and retain flags
 
 use anyhow::{Context, Result};
-use rumqttc::{AsyncClient, MqttOptions, QoS};
+use rumqttc::{AsyncClient, ConnectReturnCode, Event, MqttOptions, Packet, QoS};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info};
@@ -15,6 +17,10 @@ use tracing::{debug, error, info};
 /// MQTT Client for publishing telemetry
 pub struct MqttClient {
     client: AsyncClient,
+    /// QoS level for sensor publishes (changeable at runtime)
+    qos: AtomicU8,
+    /// Set by the event loop on CONNACK, cleared on connection errors
+    connected: Arc<AtomicBool>,
     _event_loop_handle: JoinHandle<()>,
 }
 
@@ -25,7 +31,13 @@ impl MqttClient {
     /// * `broker_url` - URL like "mqtt://localhost:1883"
     /// * `client_id` - Unique client identifier
     /// * `qos` - Quality of Service level (0, 1, or 2)
-    pub async fn new(broker_url: &str, client_id: &str, _qos: u8) -> Result<Self> {
+    /// * `credentials` - Optional username and password
+    pub async fn new(
+        broker_url: &str,
+        client_id: &str,
+        qos: u8,
+        credentials: Option<(&str, &str)>,
+    ) -> Result<Self> {
         info!(broker = broker_url, client_id = client_id, "Connecting to MQTT broker");
 
         // Parse broker URL
@@ -34,19 +46,29 @@ impl MqttClient {
         // Configure MQTT options
         let mut mqttoptions = MqttOptions::new(client_id, host, port);
         mqttoptions.set_keep_alive(Duration::from_secs(30));
+        if let Some((username, password)) = credentials {
+            mqttoptions.set_credentials(username, password);
+        }
 
         // Create client
         let (client, mut event_loop) = AsyncClient::new(mqttoptions, 10);
 
         // Spawn event loop handler task
+        let connected = Arc::new(AtomicBool::new(false));
+        let event_loop_connected = connected.clone();
         let event_loop_handle = tokio::spawn(async move {
             info!("MQTT event loop started");
             loop {
                 match event_loop.poll().await {
                     Ok(notification) => {
+                        if let Event::Incoming(Packet::ConnAck(ack)) = &notification {
+                            let accepted = ack.code == ConnectReturnCode::Success;
+                            event_loop_connected.store(accepted, Ordering::Relaxed);
+                        }
                         debug!("MQTT notification: {:?}", notification);
                     }
                     Err(e) => {
+                        event_loop_connected.store(false, Ordering::Relaxed);
                         error!("MQTT connection error: {}", e);
                         // Add exponential backoff here in Phase 4
                         tokio::time::sleep(Duration::from_secs(1)).await;
@@ -59,10 +81,32 @@ impl MqttClient {
 
         Ok(Self {
             client,
+            qos: AtomicU8::new(qos),
+            connected,
             _event_loop_handle: event_loop_handle,
         })
     }
 
+    /// Whether the client currently has an accepted broker connection
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Shared connection flag, for health reporting
+    pub fn connection_flag(&self) -> Arc<AtomicBool> {
+        self.connected.clone()
+    }
+
+    /// Change the QoS level used by [`MqttClient::publish_sensor`]
+    pub fn set_qos(&self, qos: u8) {
+        self.qos.store(qos, Ordering::Relaxed);
+    }
+
+    /// Current QoS level for sensor publishes
+    pub fn qos(&self) -> QoS {
+        qos_from_level(self.qos.load(Ordering::Relaxed))
+    }
+
     /// Publish a message to a topic
     ///
     /// # Arguments
@@ -116,7 +160,7 @@ impl MqttClient {
         retain: bool,
     ) -> Result<()> {
         let topic = Self::build_topic(prefix, node, metric);
-        self.publish(&topic, value, QoS::AtLeastOnce, retain).await
+        self.publish(&topic, value, self.qos(), retain).await
     }
 
     /// Build topic name for a sensor reading
@@ -136,19 +180,35 @@ impl MqttClient {
     }
 }
 
+/// Map a configured QoS level (0, 1, 2) to rumqttc's QoS
+///
+/// Levels above 2 are rejected by config validation; treat them as 2.
+pub fn qos_from_level(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
 /// Parse MQTT broker URL into host and port
 ///
 /// Supports:
 /// - mqtt://localhost:1883
 /// - mqtt://192.168.1.100:1883
 /// - mqtts://broker.example.com:8883 (TLS, for future)
-fn parse_broker_url(url: &str) -> Result<(String, u16)> {
+pub fn parse_broker_url(url: &str) -> Result<(String, u16)> {
     // Remove protocol prefix
     let url_without_protocol = url
         .strip_prefix("mqtt://")
         .or_else(|| url.strip_prefix("mqtts://"))
         .context("Invalid MQTT URL: must start with mqtt:// or mqtts://")?;
 
+    // The broker is just host and port, there is nowhere for a path to go
+    if url_without_protocol.contains('/') {
+        anyhow::bail!("Invalid MQTT URL: must not have a path");
+    }
+
     // Split host and port
     if let Some((host, port_str)) = url_without_protocol.split_once(':') {
         let port = port_str
@@ -182,6 +242,15 @@ mod tests {
 
         // Invalid URL
         assert!(parse_broker_url("http://localhost:1883").is_err());
+        assert!(parse_broker_url("mqtt://localhost:1883/").is_err());
+        assert!(parse_broker_url("mqtt://localhost/iiot").is_err());
+    }
+
+    #[test]
+    fn test_qos_from_level() {
+        assert_eq!(qos_from_level(0), QoS::AtMostOnce);
+        assert_eq!(qos_from_level(1), QoS::AtLeastOnce);
+        assert_eq!(qos_from_level(2), QoS::ExactlyOnce);
     }
 
     #[test]
@@ -0,0 +1,169 @@
+//! MQTT sink (Phase 3)
+//!
+//! Publishes to `<prefix>/<node_id>/<metric>`:
+//! - telemetry values, plus `<metric>/quality` for validated metrics
+//!   (filtered by the deadband, see [`crate::deadband`])
+//! - firmware reboots to `<node_id>/reboot` (retained JSON)
+//! - node status to `<node_id>/status` and `<metric>/stale` (retained)
+//!
+//! Topic prefix, QoS and deadband settings follow config reloads.
+
+use crate::availability::StatusChange;
+use crate::config::{Config, SinkConfig};
+use crate::deadband::DeadbandFilter;
+use crate::fanout::SinkEvent;
+use crate::metrics::Metrics;
+use crate::mqtt::MqttClient;
+use crate::sink::{self, SinkContext, TelemetrySink};
+use crate::telemetry::{Quality, Reading};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::watch;
+use tracing::info;
+
+pub struct MqttSink {
+    client: Arc<MqttClient>,
+    config: watch::Receiver<Config>,
+    metrics: Arc<Metrics>,
+    deadband: DeadbandFilter,
+}
+
+impl MqttSink {
+    /// Factory for the `mqtt` sink type (takes no options)
+    pub fn from_config(config: &SinkConfig, ctx: &SinkContext) -> Result<Self> {
+        sink::no_options(config)?;
+        Ok(Self {
+            client: ctx.mqtt_client()?,
+            config: ctx.config.clone(),
+            metrics: ctx.metrics.clone(),
+            deadband: DeadbandFilter::new(),
+        })
+    }
+
+    /// Publish readings to `<prefix>/<node_id>/<metric>`
+    ///
+    /// Validated metrics also get `<prefix>/<node_id>/<metric>/quality`.
+    /// Values flagged bad only go to the value topic if `publish_bad` is set.
+    /// With the deadband enabled, metrics that didn't change meaningfully are
+    /// skipped.
+    async fn publish_telemetry(&mut self, prefix: &str, readings: &[Reading], publish_bad: bool) -> Result<()> {
+        let deadband_config = self.config.borrow().mqtt_deadband.clone();
+        let now = Instant::now();
+
+        for reading in readings {
+            for metric in &reading.metrics {
+                if !self.deadband.changed(&reading.node_id, metric, &deadband_config, now) {
+                    self.metrics.mqtt_publishes_suppressed.inc();
+                    continue;
+                }
+                let retain = metric.kind.retained();
+                if let Some(quality) = metric.quality {
+                    let name = format!("{}/quality", metric.name);
+                    self.client
+                        .publish_sensor(prefix, &reading.node_id, &name, quality.as_str(), retain)
+                        .await?;
+                    if quality == Quality::Bad && !publish_bad {
+                        self.deadband.record(&reading.node_id, metric, None, now);
+                        continue;
+                    }
+                }
+                self.client
+                    .publish_sensor(prefix, &reading.node_id, &metric.name, &format_value(metric.value), retain)
+                    .await?;
+                self.deadband.record(&reading.node_id, metric, Some(metric.value), now);
+            }
+        }
+
+        info!("Published telemetry to MQTT topics");
+        Ok(())
+    }
+
+    /// Publish a node online/offline transition and mark its retained
+    /// values stale or fresh
+    async fn publish_node_status(&self, prefix: &str, change: &StatusChange) -> Result<()> {
+        let status = if change.online { "online" } else { "offline" };
+        self.client
+            .publish_sensor(prefix, &change.node, "status", status, true)
+            .await?;
+
+        let stale = (!change.online).to_string();
+        for metric in &change.retained {
+            let name = format!("{}/stale", metric);
+            self.client.publish_sensor(prefix, &change.node, &name, &stale, true).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TelemetrySink for MqttSink {
+    async fn write(&mut self, event: &SinkEvent) -> Result<()> {
+        // Pick up reloaded settings
+        if self.config.has_changed().unwrap_or(false) {
+            self.client.set_qos(self.config.borrow_and_update().mqtt.qos);
+        }
+        let (prefix, publish_bad) = {
+            let config = self.config.borrow();
+            (config.mqtt.topic_prefix.clone(), config.validation.mqtt_publish_bad)
+        };
+
+        match event {
+            SinkEvent::Telemetry { readings, .. } => {
+                let published = self.publish_telemetry(&prefix, readings, publish_bad).await;
+                match published {
+                    Ok(()) => self.metrics.mqtt_publishes.inc(),
+                    Err(_) => self.metrics.mqtt_publish_failures.inc(),
+                }
+                published.context("Failed to publish telemetry to MQTT")
+            }
+            SinkEvent::Reboots(events) => {
+                for event in events {
+                    self.client
+                        .publish_sensor(&prefix, &event.node, "reboot", &event.to_json(), true)
+                        .await
+                        .with_context(|| format!("Failed to publish reboot event for {} to MQTT", event.node))?;
+                }
+                Ok(())
+            }
+            SinkEvent::Status(changes) => {
+                for change in changes {
+                    self.publish_node_status(&prefix, change)
+                        .await
+                        .with_context(|| format!("Failed to publish node status for {} to MQTT", change.node))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn health(&self) -> bool {
+        self.client.is_connected()
+    }
+}
+
+/// Payload for a value, to 12 significant digits
+///
+/// Keeps float noise from calibration such as `22.900000000000002` off the
+/// topics without rounding small values away.
+fn format_value(value: f64) -> String {
+    format!("{:.11e}", value)
+        .parse::<f64>()
+        .unwrap_or(value)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!((24.1 - 1.2).to_string(), "22.900000000000002");
+        assert_eq!(format_value(24.1 - 1.2), "22.9");
+        assert_eq!(format_value(85000.0 * 0.001), "85");
+        assert_eq!(format_value(0.000123), "0.000123");
+        assert_eq!(format_value(-3.5), "-3.5");
+    }
+}
@@ -0,0 +1,153 @@
+//! Firmware reboot detection
+//!
+//! When the gateway STM32 resets, its uptime timestamp and reception
+//! counters (`sts.rx`, `sts.err`) start again from zero. For every node that
+//! reports counters, a packet whose timestamp went backwards or whose
+//! counters decreased is treated as a reboot:
+//! - a [`RebootEvent`] records the uptime reached before the reset
+//! - each counter gets a `<name>_total` companion that carries on from the
+//!   pre-reboot value, so dashboards never see a counter go backwards
+
+use crate::telemetry::{MetricKind, Reading};
+use serde_json::json;
+use std::collections::HashMap;
+
+/// A detected firmware reboot
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebootEvent {
+    pub node: String,
+    /// Uptime of the last packet before the reboot
+    pub previous_uptime_ms: u64,
+    /// Uptime of the first packet after it
+    pub timestamp_ms: u64,
+    /// Reboots seen from this node since the gateway started
+    pub reboots: u64,
+}
+
+impl RebootEvent {
+    /// JSON payload for MQTT
+    pub fn to_json(&self) -> String {
+        json!({
+            "previous_uptime_ms": self.previous_uptime_ms,
+            "timestamp_ms": self.timestamp_ms,
+            "reboots": self.reboots,
+        })
+        .to_string()
+    }
+}
+
+struct NodeState {
+    last_timestamp_ms: u64,
+    /// Last raw value of each counter
+    last: HashMap<String, f64>,
+    /// Sum of counter values reached before earlier reboots
+    offsets: HashMap<String, f64>,
+    reboots: u64,
+}
+
+/// Reboot state of every node that reports counters
+#[derive(Default)]
+pub struct RebootDetector {
+    nodes: HashMap<String, NodeState>,
+}
+
+impl RebootDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check one packet's readings, adding `<counter>_total` metrics
+    pub fn observe(&mut self, readings: &mut [Reading]) -> Vec<RebootEvent> {
+        let mut events = Vec::new();
+
+        for reading in readings.iter_mut() {
+            let counters: Vec<(String, f64)> = reading
+                .metrics
+                .iter()
+                .filter(|m| m.kind == MetricKind::Counter)
+                .map(|m| (m.name.clone(), m.value))
+                .collect();
+            if counters.is_empty() {
+                continue;
+            }
+
+            let ts = reading.timestamp_ms;
+            let state = self.nodes.entry(reading.node_id.clone()).or_insert_with(|| NodeState {
+                last_timestamp_ms: ts,
+                last: HashMap::new(),
+                offsets: HashMap::new(),
+                reboots: 0,
+            });
+
+            let counter_reset = counters
+                .iter()
+                .any(|(name, value)| state.last.get(name).is_some_and(|last| value < last));
+            if ts < state.last_timestamp_ms || counter_reset {
+                for (name, last) in &state.last {
+                    *state.offsets.entry(name.clone()).or_default() += last;
+                }
+                state.reboots += 1;
+                events.push(RebootEvent {
+                    node: reading.node_id.clone(),
+                    previous_uptime_ms: state.last_timestamp_ms,
+                    timestamp_ms: ts,
+                    reboots: state.reboots,
+                });
+            }
+            state.last_timestamp_ms = ts;
+
+            for (name, value) in counters {
+                let offset = state.offsets.get(&name).copied().unwrap_or(0.0);
+                reading.add_metric(&format!("{}_total", name), value + offset, None, MetricKind::Counter);
+                state.last.insert(name, value);
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(ts: u64, rx: f64, err: f64) -> Vec<Reading> {
+        vec![
+            Reading::new("node1", ts).with_metric("temperature", 21.0, Some("celsius"), MetricKind::Sensor),
+            Reading::new("stats", ts)
+                .with_metric("packets_received", rx, None, MetricKind::Counter)
+                .with_metric("crc_errors", err, None, MetricKind::Counter),
+        ]
+    }
+
+    fn total(readings: &[Reading], name: &str) -> f64 {
+        readings[1].metric(name).unwrap().value
+    }
+
+    #[test]
+    fn test_reboot_keeps_totals() {
+        let mut detector = RebootDetector::new();
+
+        let mut r = stats(10_000, 40.0, 2.0);
+        assert!(detector.observe(&mut r).is_empty());
+        assert_eq!(total(&r, "packets_received_total"), 40.0);
+        // Readings without counters are left alone
+        assert_eq!(r[0].metrics.len(), 1);
+
+        let mut r = stats(20_000, 42.0, 3.0);
+        assert!(detector.observe(&mut r).is_empty());
+
+        // Reset: uptime and counters start over
+        let mut r = stats(500, 1.0, 0.0);
+        let events = detector.observe(&mut r);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].previous_uptime_ms, events[0].reboots), (20_000, 1));
+        assert_eq!(total(&r, "packets_received_total"), 43.0);
+        assert_eq!(total(&r, "crc_errors_total"), 3.0);
+
+        // A counter decreasing is a reset even if the timestamp moved on
+        let mut r = stats(30_000, 0.0, 0.0);
+        assert_eq!(detector.observe(&mut r)[0].reboots, 2);
+        assert_eq!(total(&r, "packets_received_total"), 43.0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Configuration hot reload triggers
+//!
+//! Watches config.toml for changes and listens for SIGHUP. Each trigger
+//! tells the caller to re-read the configuration; deciding what can be
+//! applied without a restart is up to [`crate::config::Config::reloadable_from`].
+//!
+//! Reloading never touches probe-rs, so the board keeps running (and keeps
+//! its uptime counter) across config changes.
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Editors often write a file in several steps; wait this long for quiet
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Why a reload was requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadReason {
+    /// config.toml was modified, created or replaced
+    FileChanged,
+    /// SIGHUP received
+    Signal,
+}
+
+/// Stream of reload requests
+pub struct ReloadTriggers {
+    rx: mpsc::Receiver<ReloadReason>,
+    _watcher: RecommendedWatcher,
+}
+
+impl ReloadTriggers {
+    /// Start watching `path` and listening for SIGHUP
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let (tx, rx) = mpsc::channel(8);
+
+        // Watch the directory rather than the file: editors and config
+        // management tools usually replace the file, which drops a file watch
+        let path = std::fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve config path: {}", path.display()))?;
+        let dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let file_tx = tx.clone();
+        let watched = path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event) => {
+                    let relevant = matches!(
+                        event.kind,
+                        EventKind::Modify(_) | EventKind::Create(_)
+                    ) && event.paths.iter().any(|p| p == &watched);
+                    if relevant {
+                        // Full channel means a reload is already pending
+                        let _ = file_tx.try_send(ReloadReason::FileChanged);
+                    }
+                }
+                Err(e) => warn!(error = %e, "Config file watch error"),
+            }
+        })
+        .context("Failed to create config file watcher")?;
+
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    if tx.send(ReloadReason::Signal).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        info!(path = %path.display(), "Watching configuration for changes (SIGHUP also reloads)");
+
+        Ok(Self {
+            rx,
+            _watcher: watcher,
+        })
+    }
+
+    /// Wait for the next reload request, coalescing bursts of events
+    pub async fn next(&mut self) -> Option<ReloadReason> {
+        let reason = self.rx.recv().await?;
+
+        tokio::time::sleep(DEBOUNCE).await;
+        let mut coalesced = 0;
+        while self.rx.try_recv().is_ok() {
+            coalesced += 1;
+        }
+        debug!(?reason, coalesced, "Config reload triggered");
+
+        Some(reason)
+    }
+}
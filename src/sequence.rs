@@ -0,0 +1,156 @@
+//! Packet sequence tracking per node
+//!
+//! Nodes that number their packets (Week 5 Node 1: `n1.seq`, a wrapping
+//! u16) let the gateway tell what happened between two receptions:
+//! - in order: the next number
+//! - gap: numbers were skipped, those packets were lost on air
+//! - duplicate: the previous number again, i.e. a retransmission after a
+//!   lost ACK (the node resends its packet until it is acknowledged)
+//! - reset: a jump backwards (or far forwards), usually a node reboot
+//!
+//! A rolling window over the last [`LOSS_WINDOW`] expected packets gives the
+//! packet-loss ratio.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Expected packets the loss ratio is computed over
+pub const LOSS_WINDOW: usize = 100;
+
+/// Forward jumps larger than this are treated as a reset, not a gap
+const MAX_GAP: u64 = 1000;
+
+/// What a sequence number says about the packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    /// First packet seen from this node
+    First,
+    /// Next number after the previous packet
+    InOrder,
+    /// `missed` packets were skipped since the previous packet
+    Gap { missed: u64 },
+    /// Same number as the previous packet: a retransmission, should be dropped
+    Duplicate,
+    /// Numbering restarted (node rebooted or counter jumped)
+    Reset,
+}
+
+struct NodeSequence {
+    last: u64,
+    /// Received (true) or missed (false), one entry per expected packet
+    window: VecDeque<bool>,
+}
+
+impl NodeSequence {
+    fn new(seq: u64) -> Self {
+        let mut state = Self {
+            last: seq,
+            window: VecDeque::with_capacity(LOSS_WINDOW),
+        };
+        state.record(true);
+        state
+    }
+
+    fn record(&mut self, received: bool) {
+        if self.window.len() == LOSS_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(received);
+    }
+}
+
+/// Sequence state for all nodes
+pub struct SequenceTracker {
+    /// Numbers wrap to 0 at this value
+    modulus: u64,
+    nodes: HashMap<String, NodeSequence>,
+}
+
+impl SequenceTracker {
+    /// Create a tracker for sequence numbers that wrap at `modulus`
+    /// (65536 for a u16 counter)
+    pub fn new(modulus: u64) -> Self {
+        Self {
+            modulus,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Classify the next sequence number from `node`
+    pub fn observe(&mut self, node: &str, seq: u64) -> SeqEvent {
+        let seq = seq % self.modulus;
+        let Some(state) = self.nodes.get_mut(node) else {
+            self.nodes.insert(node.to_string(), NodeSequence::new(seq));
+            return SeqEvent::First;
+        };
+
+        // A step backwards is a restart, however close to the previous
+        // number: a node rebooting soon after its last boot reuses them
+        let ahead = (seq + self.modulus - state.last) % self.modulus;
+        if ahead > MAX_GAP {
+            *state = NodeSequence::new(seq);
+            return SeqEvent::Reset;
+        }
+        if ahead == 0 {
+            return SeqEvent::Duplicate;
+        }
+
+        let missed = ahead - 1;
+        for _ in 0..missed.min(LOSS_WINDOW as u64) {
+            state.record(false);
+        }
+        state.record(true);
+        state.last = seq;
+
+        if missed == 0 {
+            SeqEvent::InOrder
+        } else {
+            SeqEvent::Gap { missed }
+        }
+    }
+
+    /// Fraction of the last expected packets that were lost (0.0 - 1.0)
+    pub fn loss_ratio(&self, node: &str) -> Option<f64> {
+        let state = self.nodes.get(node)?;
+        let missed = state.window.iter().filter(|received| !**received).count();
+        Some(missed as f64 / state.window.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_events() {
+        let mut tracker = SequenceTracker::new(65536);
+
+        assert_eq!(tracker.observe("node1", 65534), SeqEvent::First);
+        assert_eq!(tracker.observe("node1", 65535), SeqEvent::InOrder);
+        // Wraps around without a gap
+        assert_eq!(tracker.observe("node1", 0), SeqEvent::InOrder);
+        assert_eq!(tracker.observe("node1", 3), SeqEvent::Gap { missed: 2 });
+        // Retransmission of the last packet
+        assert_eq!(tracker.observe("node1", 3), SeqEvent::Duplicate);
+        assert_eq!(tracker.observe("node1", 3), SeqEvent::Duplicate);
+
+        // 2 lost out of 6 expected
+        let loss = tracker.loss_ratio("node1").unwrap();
+        assert!((loss - 2.0 / 6.0).abs() < 1e-9);
+
+        // Reboot at seq 5: the restarted numbers are not duplicates
+        assert_eq!(tracker.observe("node2", 0), SeqEvent::First);
+        for seq in 1..=5 {
+            assert_eq!(tracker.observe("node2", seq), SeqEvent::InOrder);
+        }
+        assert_eq!(tracker.observe("node2", 0), SeqEvent::Reset);
+        assert_eq!(tracker.observe("node2", 1), SeqEvent::InOrder);
+
+        // Reboot: numbering starts over, window restarts
+        assert_eq!(tracker.observe("node1", 40000), SeqEvent::Reset);
+        assert_eq!(tracker.loss_ratio("node1"), Some(0.0));
+
+        // Nodes are tracked independently
+        assert_eq!(tracker.observe("node3", 3), SeqEvent::First);
+        assert_eq!(tracker.loss_ratio("node9"), None);
+    }
+}
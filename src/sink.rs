@@ -0,0 +1,345 @@
+//! Pluggable telemetry sinks
+//!
+//! A sink receives every [`SinkEvent`] from the processor through its own
+//! queue (see [`crate::fanout`]) and runs in its own worker task. Sinks are
+//! built from the `[[sinks]]` config list by a [`SinkRegistry`], which maps
+//! each `type` to a factory:
+//! - `mqtt`: [`crate::mqtt_sink::MqttSink`]
+//! - `influxdb`: [`crate::influxdb_sink::InfluxDbSink`]
+//! - `file`: [`crate::file_sink::FileSink`]
+//!
+//! To add a sink, implement [`TelemetrySink`] and register a factory: in
+//! [`SinkRegistry::builtin`] for sinks shipped with the gateway, or on the
+//! registry a program embedding the gateway passes to
+//! [`crate::gateway::run`]. `[[sinks]]` types are validated against the
+//! registry in use.
+
+use crate::config::{Config, SinkConfig, ValidationError, ValidationErrors};
+use crate::fanout::{EventQueue, SinkEvent};
+use crate::file_sink::FileSink;
+use crate::influxdb::InfluxDbClient;
+use crate::influxdb_sink::InfluxDbSink;
+use crate::metrics::Metrics;
+use crate::mqtt::MqttClient;
+use crate::mqtt_sink::MqttSink;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// How often workers call [`TelemetrySink::flush`] and check health
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Destination for processed telemetry
+#[async_trait]
+pub trait TelemetrySink: Send + Sync {
+    /// Deliver one event; sinks ignore events they have no use for
+    async fn write(&mut self, event: &SinkEvent) -> Result<()>;
+
+    /// Push out buffered data; called periodically and before shutdown
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the sink can currently deliver
+    async fn health(&self) -> bool {
+        true
+    }
+
+    /// Flush and release resources once the queue is drained
+    async fn shutdown(&mut self) -> Result<()> {
+        self.flush().await
+    }
+}
+
+/// Shared client connection a sink type writes through
+///
+/// The gateway only connects (and waits for) the ones an enabled sink uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Connection {
+    Mqtt,
+    InfluxDb,
+}
+
+/// Shared resources available to sink factories
+pub struct SinkContext {
+    /// Set if an enabled sink uses [`Connection::Mqtt`]
+    pub mqtt: Option<Arc<MqttClient>>,
+    /// Set if an enabled sink uses [`Connection::InfluxDb`]
+    pub influxdb: Option<Arc<InfluxDbClient>>,
+    /// Live configuration, for settings that can be reloaded
+    pub config: watch::Receiver<Config>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl SinkContext {
+    /// The MQTT client, for sink types registered with [`Connection::Mqtt`]
+    pub fn mqtt_client(&self) -> Result<Arc<MqttClient>> {
+        self.mqtt.clone().context("MQTT is not connected")
+    }
+
+    /// The InfluxDB client, for sink types registered with [`Connection::InfluxDb`]
+    pub fn influxdb_client(&self) -> Result<Arc<InfluxDbClient>> {
+        self.influxdb.clone().context("InfluxDB is not connected")
+    }
+}
+
+/// Builds a sink from its `[[sinks]]` entry
+pub type SinkFactory = Box<dyn Fn(&SinkConfig, &SinkContext) -> Result<Box<dyn TelemetrySink>> + Send + Sync>;
+
+/// Checks a sink's type-specific options without building it
+pub type OptionsCheck = Box<dyn Fn(&SinkConfig) -> Result<()> + Send + Sync>;
+
+/// A registered sink type
+struct SinkType {
+    factory: SinkFactory,
+    connections: Vec<Connection>,
+}
+
+/// Sink types by `type`
+#[derive(Default)]
+pub struct SinkRegistry {
+    types: BTreeMap<String, SinkType>,
+    checks: BTreeMap<String, OptionsCheck>,
+}
+
+impl SinkRegistry {
+    /// Registry without any sink types
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the sinks shipped with the gateway
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register_using("mqtt", &[Connection::Mqtt], |config, ctx| {
+            Ok(Box::new(MqttSink::from_config(config, ctx)?))
+        });
+        registry.register_using("influxdb", &[Connection::InfluxDb], |config, ctx| {
+            Ok(Box::new(InfluxDbSink::from_config(config, ctx)?))
+        });
+        registry.register("file", |config, _| Ok(Box::new(FileSink::from_config(config)?)));
+        registry.register_options_check("mqtt", no_options);
+        registry.register_options_check("influxdb", no_options);
+        registry.register_options_check("file", |config| FileSink::options(config).map(|_| ()));
+        registry
+    }
+
+    /// Add (or replace) the factory for a sink type that needs no shared
+    /// connection
+    pub fn register<F>(&mut self, kind: &str, factory: F)
+    where
+        F: Fn(&SinkConfig, &SinkContext) -> Result<Box<dyn TelemetrySink>> + Send + Sync + 'static,
+    {
+        self.register_using(kind, &[], factory);
+    }
+
+    /// Add (or replace) the factory for a sink type writing through the
+    /// given connections
+    pub fn register_using<F>(&mut self, kind: &str, connections: &[Connection], factory: F)
+    where
+        F: Fn(&SinkConfig, &SinkContext) -> Result<Box<dyn TelemetrySink>> + Send + Sync + 'static,
+    {
+        let sink_type = SinkType {
+            factory: Box::new(factory),
+            connections: connections.to_vec(),
+        };
+        self.types.insert(kind.to_string(), sink_type);
+    }
+
+    /// Check a sink type's options when the config is validated, so
+    /// `check-config` and reloads catch mistakes that would otherwise only
+    /// fail the factory at startup
+    pub fn register_options_check<F>(&mut self, kind: &str, check: F)
+    where
+        F: Fn(&SinkConfig) -> Result<()> + Send + Sync + 'static,
+    {
+        self.checks.insert(kind.to_string(), Box::new(check));
+    }
+
+    /// Registered sink types
+    pub fn kinds(&self) -> Vec<&str> {
+        self.types.keys().map(String::as_str).collect()
+    }
+
+    /// Connections the enabled sinks write through
+    pub fn connections(&self, configs: &[SinkConfig]) -> BTreeSet<Connection> {
+        configs
+            .iter()
+            .filter(|config| config.enabled)
+            .filter_map(|config| self.types.get(&config.kind))
+            .flat_map(|sink_type| sink_type.connections.iter().copied())
+            .collect()
+    }
+
+    /// Check that every `[[sinks]]` entry has a registered type, and the
+    /// options of enabled ones
+    pub fn validation_errors(&self, configs: &[SinkConfig]) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (i, config) in configs.iter().enumerate() {
+            if !self.types.contains_key(&config.kind) {
+                errors.push(ValidationError {
+                    path: format!("sinks[{}].type", i),
+                    message: format!(
+                        "unknown sink type {:?} (expected one of: {})",
+                        config.kind,
+                        self.kinds().join(", ")
+                    ),
+                });
+                continue;
+            }
+            let Some(check) = self.checks.get(&config.kind).filter(|_| config.enabled) else {
+                continue;
+            };
+            if let Err(e) = check(config) {
+                errors.push(ValidationError {
+                    path: format!("sinks[{}]", i),
+                    // TOML errors end in a newline
+                    message: format!("{:#}", e).trim_end().to_string(),
+                });
+            }
+        }
+        errors
+    }
+
+    /// [`SinkRegistry::validation_errors`] as a single error
+    pub fn validate(&self, configs: &[SinkConfig]) -> Result<()> {
+        ValidationErrors::check(self.validation_errors(configs))
+    }
+
+    /// Build the enabled sinks, paired with their names
+    pub fn build(&self, configs: &[SinkConfig], ctx: &SinkContext) -> Result<Vec<(String, Box<dyn TelemetrySink>)>> {
+        let mut sinks = Vec::new();
+        for config in configs.iter().filter(|c| c.enabled) {
+            let sink_type = self
+                .types
+                .get(&config.kind)
+                .with_context(|| format!("Unknown sink type: {}", config.kind))?;
+            let sink = (sink_type.factory)(config, ctx).with_context(|| format!("Failed to create sink {}", config.name()))?;
+            sinks.push((config.name().to_string(), sink));
+        }
+        Ok(sinks)
+    }
+}
+
+/// Parse a sink's type-specific settings
+pub fn options<T: DeserializeOwned>(config: &SinkConfig) -> Result<T> {
+    toml::Value::Table(config.options.clone())
+        .try_into()
+        .with_context(|| format!("invalid options for sink type {}", config.kind))
+}
+
+/// Reject settings on a sink type that takes none
+pub fn no_options(config: &SinkConfig) -> Result<()> {
+    if let Some(key) = config.options.keys().next() {
+        anyhow::bail!("unknown option {:?} for sink type {}", key, config.kind);
+    }
+    Ok(())
+}
+
+/// Sink worker: deliver queued events until the queue is closed and drained
+pub async fn run(name: String, mut sink: Box<dyn TelemetrySink>, queue: Arc<EventQueue>, metrics: Arc<Metrics>) {
+    let depth = metrics.sink_queue_depth.with_label_values(&[name.as_str()]);
+    let healthy = metrics.sink_healthy.with_label_values(&[name.as_str()]);
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            event = queue.pop() => {
+                let Some(event) = event else {
+                    break;
+                };
+                depth.set(queue.len() as i64);
+                if let Err(e) = sink.write(&event).await {
+                    error!(sink = %name, error = %format!("{:#}", e), "Sink write failed");
+                }
+            }
+            _ = flush.tick() => {
+                if let Err(e) = sink.flush().await {
+                    warn!(sink = %name, error = %format!("{:#}", e), "Sink flush failed");
+                }
+                healthy.set(i64::from(sink.health().await));
+            }
+        }
+    }
+
+    if let Err(e) = sink.shutdown().await {
+        error!(sink = %name, error = %format!("{:#}", e), "Sink shutdown failed");
+    }
+    info!(sink = %name, "Sink stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueueConfig;
+    use crate::fanout::SinkQueue;
+    use std::sync::Mutex;
+
+    /// Records what the worker does with it
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl TelemetrySink for Recorder {
+        async fn write(&mut self, event: &SinkEvent) -> Result<()> {
+            if let SinkEvent::Telemetry { received_secs, .. } = event {
+                self.0.lock().unwrap().push(format!("write {}", received_secs));
+            }
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> Result<()> {
+            self.0.lock().unwrap().push("shutdown".to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry_validation() {
+        let mut registry = SinkRegistry::builtin();
+        let configs = [SinkConfig::new("mqtt"), SinkConfig::new("kafka")];
+        let errors = registry.validation_errors(&configs);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "sinks[1].type");
+
+        // Types registered by an embedding program are accepted
+        registry.register("kafka", |_, _| Ok(Box::new(Recorder(Arc::default()))));
+        assert!(registry.validate(&configs).is_ok());
+
+        // Options are checked without building the sink
+        let mut file = SinkConfig::new("file");
+        file.options.insert("retention".to_string(), toml::Value::Integer(30));
+        let errors = registry.validation_errors(&[file]);
+        assert_eq!(errors[0].path, "sinks[0]");
+        assert!(errors[0].message.contains("unknown field `retention`"), "{}", errors[0]);
+
+        // Only enabled sinks' connections are opened
+        let mut influxdb = SinkConfig::new("influxdb");
+        influxdb.enabled = false;
+        let configs = [SinkConfig::new("mqtt"), influxdb, SinkConfig::new("file")];
+        assert_eq!(registry.connections(&configs), BTreeSet::from([Connection::Mqtt]));
+    }
+
+    #[tokio::test]
+    async fn test_worker_drains_queue() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::new(SinkQueue::new(&QueueConfig::default()));
+        for received_secs in [1, 2] {
+            let event = SinkEvent::Telemetry {
+                readings: Vec::new(),
+                received_secs,
+            };
+            queue.push(Arc::new(event)).await;
+        }
+        queue.close();
+
+        let metrics = Arc::new(Metrics::new().unwrap());
+        run("test".to_string(), Box::new(Recorder(log.clone())), queue, metrics).await;
+        assert_eq!(*log.lock().unwrap(), ["write 1", "write 2", "shutdown"]);
+    }
+}
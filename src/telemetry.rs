@@ -0,0 +1,161 @@
+//! Node-agnostic telemetry model
+//!
+//! Every input format is mapped into [`Reading`]s: one per source node, each
+//! with a set of named metrics and optional radio link metadata. The MQTT and
+//! InfluxDB sinks only ever see readings, so supporting a new node means
+//! extending the mapping layer (e.g. [`crate::week5`]) rather than the sinks.
+//!
+//! Sinks publish a metric to `<prefix>/<node_id>/<name>` and write it
+//! to InfluxDB as measurement `<name>` tagged with `node` and `unit`.
+
+use std::fmt;
+
+/// Metrics reported by one node in one packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    /// Source node, used as the topic level and `node` tag (e.g. "node1")
+    pub node_id: String,
+    /// Firmware timestamp of the packet, in milliseconds since boot
+    pub timestamp_ms: u64,
+    pub metrics: Vec<Metric>,
+    /// Radio link the reading arrived over, if it came in via LoRa
+    pub link: Option<LinkMetadata>,
+    /// Sender's packet sequence number, if the node sends one
+    pub seq: Option<u32>,
+}
+
+/// One named value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// Metric name, used as the last topic level and measurement name
+    pub name: String,
+    pub value: f64,
+    /// Unit tag, e.g. "celsius" (None for dimensionless counters)
+    pub unit: Option<String>,
+    pub kind: MetricKind,
+    /// Plausibility verdict, `None` if no validation rule covers the metric
+    pub quality: Option<Quality>,
+}
+
+/// What a metric describes, which decides how sinks treat it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// Physical measurement from a sensor
+    Sensor,
+    /// Radio link quality (RSSI, SNR, ...)
+    Link,
+    /// Monotonic counter (packets received, errors, ...)
+    Counter,
+}
+
+impl MetricKind {
+    /// Whether MQTT should retain the last value for new subscribers
+    ///
+    /// Only sensor values are meaningful on their own; link quality and
+    /// counters are only interesting as a live series.
+    pub fn retained(self) -> bool {
+        matches!(self, MetricKind::Sensor)
+    }
+}
+
+/// Plausibility of a value, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Quality {
+    Good,
+    /// Possible but unusual (too fast a change, stuck sensor)
+    Suspect,
+    /// Physically implausible (out of range)
+    Bad,
+}
+
+impl Quality {
+    /// Value of the `quality` tag / topic
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Quality::Good => "good",
+            Quality::Suspect => "suspect",
+            Quality::Bad => "bad",
+        }
+    }
+}
+
+/// Radio link metadata for a reading received over LoRa
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkMetadata {
+    /// Received signal strength in dBm
+    pub rssi_dbm: f64,
+    /// Signal-to-noise ratio in dB
+    pub snr_db: f64,
+}
+
+impl Reading {
+    /// Create a reading without metrics or link metadata
+    pub fn new(node_id: impl Into<String>, timestamp_ms: u64) -> Self {
+        Self {
+            node_id: node_id.into(),
+            timestamp_ms,
+            metrics: Vec::new(),
+            link: None,
+            seq: None,
+        }
+    }
+
+    /// Add a metric (builder style)
+    pub fn with_metric(mut self, name: &str, value: f64, unit: Option<&str>, kind: MetricKind) -> Self {
+        self.add_metric(name, value, unit, kind);
+        self
+    }
+
+    /// Add a metric
+    pub fn add_metric(&mut self, name: &str, value: f64, unit: Option<&str>, kind: MetricKind) {
+        self.metrics.push(Metric {
+            name: name.to_string(),
+            value,
+            unit: unit.map(str::to_string),
+            kind,
+            quality: None,
+        });
+    }
+
+    /// Attach link metadata (builder style)
+    pub fn with_link(mut self, link: LinkMetadata) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    /// Look up a metric by name
+    pub fn metric(&self, name: &str) -> Option<&Metric> {
+        self.metrics.iter().find(|m| m.name == name)
+    }
+}
+
+impl fmt::Display for Reading {
+    /// Compact `name=value` summary for log lines
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, metric) in self.metrics.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", metric.name, metric.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_builder() {
+        let reading = Reading::new("node3", 1000)
+            .with_metric("pressure", 1013.2, Some("hpa"), MetricKind::Sensor)
+            .with_metric("reboots", 2.0, None, MetricKind::Counter);
+
+        assert_eq!(reading.metric("pressure").unwrap().unit.as_deref(), Some("hpa"));
+        assert!(reading.metric("pressure").unwrap().kind.retained());
+        assert!(!reading.metric("reboots").unwrap().kind.retained());
+        assert!(reading.metric("humidity").is_none());
+        assert_eq!(reading.to_string(), "pressure=1013.2 reboots=2");
+    }
+}
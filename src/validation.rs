@@ -0,0 +1,148 @@
+//! Plausibility checks between the channel and the sinks
+//!
+//! Every metric covered by a rule in `[validation.rules]` gets a
+//! [`Quality`] flag:
+//! - bad: outside `min`/`max` (e.g. a BME680 glitch reporting -40 °C)
+//! - suspect: changed faster than `max_rate_per_sec`, or the same value
+//!   `stuck_count` times in a row
+//! - good: otherwise
+//!
+//! Rates use the firmware timestamps, so replayed captures are judged the
+//! same way as live data.
+
+use crate::config::{MetricRule, ValidationConfig};
+use crate::telemetry::{Quality, Reading};
+use std::collections::HashMap;
+
+/// Last accepted value of one node's metric
+struct History {
+    value: f64,
+    timestamp_ms: u64,
+    /// Consecutive packets with this exact value
+    repeats: u32,
+}
+
+/// Stateful validator, one per pipeline
+#[derive(Default)]
+pub struct Validator {
+    history: HashMap<(String, String), History>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flag every metric in `readings` that has a rule
+    pub fn check(&mut self, readings: &mut [Reading], config: &ValidationConfig) {
+        if !config.enabled {
+            return;
+        }
+
+        for reading in readings.iter_mut() {
+            for metric in reading.metrics.iter_mut() {
+                let rule = config
+                    .rules
+                    .get(&format!("{}/{}", reading.node_id, metric.name))
+                    .or_else(|| config.rules.get(&metric.name));
+                let Some(rule) = rule else {
+                    continue;
+                };
+
+                let key = (reading.node_id.clone(), metric.name.clone());
+                let previous = self.history.get(&key);
+                let quality = judge(rule, metric.value, reading.timestamp_ms, previous);
+
+                let repeats = match previous {
+                    Some(p) if p.value == metric.value => p.repeats + 1,
+                    _ => 1,
+                };
+                // Don't let an out-of-range glitch become the baseline
+                if quality != Quality::Bad {
+                    self.history.insert(
+                        key,
+                        History {
+                            value: metric.value,
+                            timestamp_ms: reading.timestamp_ms,
+                            repeats,
+                        },
+                    );
+                }
+                metric.quality = Some(quality);
+            }
+        }
+    }
+}
+
+fn judge(rule: &MetricRule, value: f64, timestamp_ms: u64, previous: Option<&History>) -> Quality {
+    if !value.is_finite() || rule.min.is_some_and(|min| value < min) || rule.max.is_some_and(|max| value > max) {
+        return Quality::Bad;
+    }
+
+    let Some(previous) = previous else {
+        return Quality::Good;
+    };
+
+    // Only forward in time; a timestamp reset means the node rebooted
+    if let Some(max_rate) = rule.max_rate_per_sec {
+        if timestamp_ms > previous.timestamp_ms {
+            let secs = (timestamp_ms - previous.timestamp_ms) as f64 / 1000.0;
+            if (value - previous.value).abs() / secs > max_rate {
+                return Quality::Suspect;
+            }
+        }
+    }
+
+    if let Some(stuck_count) = rule.stuck_count {
+        if previous.value == value && previous.repeats + 1 >= stuck_count {
+            return Quality::Suspect;
+        }
+    }
+
+    Quality::Good
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::MetricKind;
+
+    fn reading(ts: u64, temperature: f64, gas: f64) -> Vec<Reading> {
+        vec![Reading::new("node1", ts)
+            .with_metric("temperature", temperature, Some("celsius"), MetricKind::Sensor)
+            .with_metric("gas_resistance", gas, Some("ohms"), MetricKind::Sensor)
+            .with_metric("rssi", -70.0, Some("dbm"), MetricKind::Link)]
+    }
+
+    fn qualities(readings: &[Reading]) -> Vec<Option<Quality>> {
+        readings[0].metrics.iter().map(|m| m.quality).collect()
+    }
+
+    #[test]
+    fn test_validation_flags() {
+        let mut config = ValidationConfig::default();
+        config.rules.get_mut("gas_resistance").unwrap().stuck_count = Some(3);
+        let mut validator = Validator::new();
+
+        let mut r = reading(0, 22.0, 90000.0);
+        validator.check(&mut r, &config);
+        // Metrics without a rule stay unflagged
+        assert_eq!(qualities(&r), vec![Some(Quality::Good), Some(Quality::Good), None]);
+
+        // Glitch: out of range, and 0 Ω gas resistance
+        let mut r = reading(2000, -40.0, 0.0);
+        validator.check(&mut r, &config);
+        assert_eq!(qualities(&r)[..2], [Some(Quality::Bad), Some(Quality::Bad)]);
+
+        // Compared against the last value before the glitch: +5 °C in 4 s
+        // is too fast
+        let mut r = reading(4000, 27.0, 90000.0);
+        validator.check(&mut r, &config);
+        assert_eq!(qualities(&r)[..2], [Some(Quality::Suspect), Some(Quality::Good)]);
+
+        // Third identical gas value in a row
+        let mut r = reading(6000, 27.5, 90000.0);
+        validator.check(&mut r, &config);
+        assert_eq!(qualities(&r)[..2], [Some(Quality::Good), Some(Quality::Suspect)]);
+    }
+}
@@ -0,0 +1,440 @@
+//! Week 5 gateway firmware JSON format
+//!
+//! The firmware sends one packet per LoRa reception:
+//! `{"v":2,"ts":12000,"id":"N2","n1":{"seq":42,...},"n2":{...},"sig":{...},"sts":{...}}`
+//!
+//! `v` is the schema version (packets without it are version 1; version 2
+//! adds Node 1's LoRa sequence number `n1.seq`). Each
+//! version has its own field table, so old and new firmware can feed the
+//! same gateway. Decoding is tolerant: a missing or malformed field only
+//! loses that value, and unknown fields are kept as extra metrics. Every
+//! deviation from the schema is reported as a [`Mismatch`].
+//!
+//! [`decode`] maps a packet into the generic model, keeping the topic layout
+//! dashboards already use:
+//! - `node1`: remote BME680 sensor (temperature, humidity, gas resistance),
+//!   with the LoRa link metadata attached
+//! - `node2`: gateway SHT3x sensor (only values that are present)
+//! - `signal`: RSSI/SNR of the Node 1 link
+//! - `stats`: gateway reception counters
+//! - unknown sections become a node named after the key (`n3` → `node3`),
+//!   unknown top-level numbers go to the `gateway` node
+
+use crate::telemetry::{LinkMetadata, MetricKind, Reading};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Newest schema version this gateway understands
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// `n1.seq` is a u16 on the firmware side and wraps at this value
+pub const SEQ_MODULUS: u64 = 1 << 16;
+
+/// Node that receives unknown top-level values
+const GATEWAY_NODE: &str = "gateway";
+
+/// One known field of a section
+struct Field {
+    key: &'static str,
+    metric: &'static str,
+    unit: Option<&'static str>,
+    required: bool,
+}
+
+/// One known section (JSON object) of a packet
+struct Section {
+    key: &'static str,
+    node: &'static str,
+    kind: MetricKind,
+    fields: &'static [Field],
+    /// Key of the sender's sequence number (required when set)
+    seq: Option<&'static str>,
+}
+
+const fn field(key: &'static str, metric: &'static str, unit: Option<&'static str>, required: bool) -> Field {
+    Field { key, metric, unit, required }
+}
+
+const N1_FIELDS: &[Field] = &[
+    field("t", "temperature", Some("celsius"), true),
+    field("h", "humidity", Some("percent"), true),
+    field("g", "gas_resistance", Some("ohms"), true),
+];
+
+const N2: Section = Section {
+    key: "n2",
+    node: "node2",
+    kind: MetricKind::Sensor,
+    fields: &[
+        field("t", "temperature", Some("celsius"), false),
+        field("h", "humidity", Some("percent"), false),
+    ],
+    seq: None,
+};
+
+const SIG: Section = Section {
+    key: "sig",
+    node: "signal",
+    kind: MetricKind::Link,
+    fields: &[
+        field("rssi", "rssi", Some("dbm"), true),
+        field("snr", "snr", Some("db"), true),
+    ],
+    seq: None,
+};
+
+const STS: Section = Section {
+    key: "sts",
+    node: "stats",
+    kind: MetricKind::Counter,
+    fields: &[
+        field("rx", "packets_received", None, true),
+        field("err", "crc_errors", None, true),
+    ],
+    seq: None,
+};
+
+/// Schema version 1: the original Week 5 format
+const V1_SECTIONS: &[Section] = &[
+    Section {
+        key: "n1",
+        node: "node1",
+        kind: MetricKind::Sensor,
+        fields: N1_FIELDS,
+        seq: None,
+    },
+    N2,
+    SIG,
+    STS,
+];
+
+/// Schema version 2: v1 plus Node 1's LoRa sequence number (`n1.seq`)
+const V2_SECTIONS: &[Section] = &[
+    Section {
+        key: "n1",
+        node: "node1",
+        kind: MetricKind::Sensor,
+        fields: N1_FIELDS,
+        seq: Some("seq"),
+    },
+    N2,
+    SIG,
+    STS,
+];
+
+/// Top-level keys that aren't sections (same in all versions)
+const TOP_LEVEL: &[&str] = &["v", "ts", "id"];
+
+/// Field table for a schema version, `None` if unknown
+fn schema(version: u64) -> Option<&'static [Section]> {
+    match version {
+        1 => Some(V1_SECTIONS),
+        2 => Some(V2_SECTIONS),
+        _ => None,
+    }
+}
+
+/// Node and metric a raw packet path maps to, e.g. `sts.err` → `stats/crc_errors`
+///
+/// Lets users refer to values by the names they see in the firmware JSON.
+pub fn resolve(path: &str) -> Option<(&'static str, &'static str)> {
+    let (section, key) = path.split_once('.')?;
+    let sections = schema(SCHEMA_VERSION).expect("newest schema is known");
+    let section = sections.iter().find(|s| s.key == section)?;
+    let field = section.fields.iter().find(|f| f.key == key)?;
+    Some((section.node, field.metric))
+}
+
+/// How a packet deviated from its schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MismatchKind {
+    /// `v` is newer than this gateway (decoded with the newest known schema)
+    UnknownVersion,
+    /// A required field is absent
+    MissingField,
+    /// A field not in the schema (kept as an extra metric if numeric)
+    UnknownField,
+    /// A field with the wrong type or an unusable name (dropped)
+    InvalidField,
+}
+
+impl MismatchKind {
+    /// Label value for the mismatch counter
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MismatchKind::UnknownVersion => "unknown_version",
+            MismatchKind::MissingField => "missing_field",
+            MismatchKind::UnknownField => "unknown_field",
+            MismatchKind::InvalidField => "invalid_field",
+        }
+    }
+}
+
+/// One schema deviation, e.g. `missing_field n1.g`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mismatch {
+    pub kind: MismatchKind,
+    /// Dotted JSON path of the field (or the version for unknown versions)
+    pub field: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind.as_str(), self.field)
+    }
+}
+
+/// Result of decoding one packet
+#[derive(Debug, Clone)]
+pub struct Decoded {
+    /// Schema version the packet declared
+    pub version: u64,
+    pub readings: Vec<Reading>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Decoded {
+    /// Firmware reception counters (`sts.rx`, `sts.err`), if present
+    pub fn counters(&self) -> (Option<f64>, Option<f64>) {
+        let stats = self.readings.iter().find(|r| r.node_id == "stats");
+        let value = |name| stats.and_then(|r| r.metric(name)).map(|m| m.value);
+        (value("packets_received"), value("crc_errors"))
+    }
+}
+
+/// Decode a packet; only fails if it isn't a JSON object at all
+pub fn decode(json: &str) -> Result<Decoded, serde_json::Error> {
+    let root: Map<String, Value> = serde_json::from_str(json)?;
+    Ok(decode_object(&root))
+}
+
+fn decode_object(root: &Map<String, Value>) -> Decoded {
+    let mut mismatches = Vec::new();
+    let mut mismatch = |kind, field: String| mismatches.push(Mismatch { kind, field });
+
+    let version = match root.get("v") {
+        None => 1,
+        Some(v) => v.as_u64().unwrap_or_else(|| {
+            mismatch(MismatchKind::InvalidField, "v".to_string());
+            1
+        }),
+    };
+    let sections = schema(version).unwrap_or_else(|| {
+        mismatch(MismatchKind::UnknownVersion, version.to_string());
+        schema(SCHEMA_VERSION).expect("newest schema is known")
+    });
+
+    let ts = match root.get("ts").map(Value::as_u64) {
+        Some(Some(ts)) => ts,
+        Some(None) => {
+            mismatch(MismatchKind::InvalidField, "ts".to_string());
+            0
+        }
+        None => {
+            mismatch(MismatchKind::MissingField, "ts".to_string());
+            0
+        }
+    };
+
+    let mut readings = Vec::new();
+
+    // Known sections, in schema order
+    for section in sections {
+        let mut reading = Reading::new(section.node, ts);
+        match root.get(section.key) {
+            Some(Value::Object(obj)) => {
+                if let Some(key) = section.seq {
+                    let path = format!("{}.{}", section.key, key);
+                    match obj.get(key).map(|v| v.as_u64().and_then(|n| u32::try_from(n).ok())) {
+                        Some(Some(seq)) => reading.seq = Some(seq),
+                        Some(None) => mismatch(MismatchKind::InvalidField, path),
+                        None => mismatch(MismatchKind::MissingField, path),
+                    }
+                }
+                for f in section.fields {
+                    match obj.get(f.key).map(number) {
+                        Some(Some(value)) => {
+                            reading = reading.with_metric(f.metric, value, f.unit, section.kind);
+                        }
+                        Some(None) => mismatch(MismatchKind::InvalidField, format!("{}.{}", section.key, f.key)),
+                        None if f.required => {
+                            mismatch(MismatchKind::MissingField, format!("{}.{}", section.key, f.key))
+                        }
+                        None => {}
+                    }
+                }
+                let known = |key: &str| section.seq == Some(key) || section.fields.iter().any(|f| f.key == key);
+                for (key, value) in obj.iter().filter(|(k, _)| !known(k)) {
+                    let path = format!("{}.{}", section.key, key);
+                    reading = extra_metric(reading, key, value, section.kind, path, &mut mismatch);
+                }
+            }
+            Some(_) => mismatch(MismatchKind::InvalidField, section.key.to_string()),
+            None => {
+                if section.fields.iter().any(|f| f.required) {
+                    mismatch(MismatchKind::MissingField, section.key.to_string());
+                }
+            }
+        }
+        readings.push(reading);
+    }
+
+    // Unknown sections and top-level values
+    let mut gateway = Reading::new(GATEWAY_NODE, ts);
+    for (key, value) in root {
+        if TOP_LEVEL.contains(&key.as_str()) || sections.iter().any(|s| s.key == key) {
+            continue;
+        }
+        match value {
+            Value::Object(obj) if is_valid_name(key) => {
+                mismatch(MismatchKind::UnknownField, key.clone());
+                let mut reading = Reading::new(section_node(key), ts);
+                for (field, value) in obj {
+                    let path = format!("{}.{}", key, field);
+                    reading = extra_metric(reading, field, value, MetricKind::Sensor, path, &mut mismatch);
+                }
+                readings.push(reading);
+            }
+            _ => {
+                gateway = extra_metric(gateway, key, value, MetricKind::Sensor, key.clone(), &mut mismatch);
+            }
+        }
+    }
+    readings.push(gateway);
+
+    // The signal section describes the link Node 1 arrived over
+    let link = readings.iter().find(|r| r.node_id == "signal").and_then(|r| {
+        Some(LinkMetadata {
+            rssi_dbm: r.metric("rssi")?.value,
+            snr_db: r.metric("snr")?.value,
+        })
+    });
+    if let (Some(link), Some(node1)) = (link, readings.iter_mut().find(|r| r.node_id == "node1")) {
+        node1.link = Some(link);
+    }
+
+    readings.retain(|r| !r.metrics.is_empty());
+
+    Decoded {
+        version,
+        readings,
+        mismatches,
+    }
+}
+
+/// Keep an unknown field as a metric if it is a number (or bool)
+fn extra_metric(
+    reading: Reading,
+    key: &str,
+    value: &Value,
+    kind: MetricKind,
+    path: String,
+    mismatch: &mut impl FnMut(MismatchKind, String),
+) -> Reading {
+    match number(value) {
+        Some(value) if is_valid_name(key) => {
+            mismatch(MismatchKind::UnknownField, path);
+            reading.with_metric(key, value, None, kind)
+        }
+        _ => {
+            mismatch(MismatchKind::InvalidField, path);
+            reading
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Node id for an unknown section, following the `n1` → `node1` convention
+fn section_node(key: &str) -> String {
+    match key.strip_prefix('n') {
+        Some(num) if !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()) => format!("node{}", num),
+        _ => key.to_string(),
+    }
+}
+
+/// Names become MQTT topic levels and measurement names
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_v1() {
+        let json = r#"{"ts":12000,"id":"N2","n1":{"t":22.46,"h":48.8,"g":101169},"n2":{"h":40.5},"sig":{"rssi":-72,"snr":13},"sts":{"rx":42,"err":1}}"#;
+        let decoded = decode(json).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert!(decoded.mismatches.is_empty());
+
+        let readings = &decoded.readings;
+        let nodes: Vec<&str> = readings.iter().map(|r| r.node_id.as_str()).collect();
+        assert_eq!(nodes, vec!["node1", "node2", "signal", "stats"]);
+
+        let node1 = &readings[0];
+        assert_eq!(node1.timestamp_ms, 12000);
+        assert_eq!(node1.metric("temperature").unwrap().value.to_string(), "22.46");
+        assert_eq!(node1.link.unwrap().rssi_dbm, -72.0);
+        assert_eq!(node1.seq, None);
+
+        // Missing SHT3x temperature is left out, not zero
+        assert!(readings[1].metric("temperature").is_none());
+        assert_eq!(readings[1].metric("humidity").unwrap().value, 40.5);
+
+        assert_eq!(readings[3].metric("crc_errors").unwrap().kind, MetricKind::Counter);
+        assert_eq!(decoded.counters(), (Some(42.0), Some(1.0)));
+    }
+
+    #[test]
+    fn test_decode_tolerates_schema_changes() {
+        // Newer firmware: unknown version, renamed key, new section and field
+        let json = r#"{"v":3,"ts":5,"n1":{"temp":21.5,"h":40.0,"g":9000},"n3":{"p":1013.2},"bat":3.7,"sig":{"rssi":-80,"snr":"7"},"sts":{"rx":1,"err":0}}"#;
+        let decoded = decode(json).unwrap();
+        assert_eq!(decoded.version, 3);
+
+        let mismatches: Vec<String> = decoded.mismatches.iter().map(|m| m.to_string()).collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                "unknown_version 3",
+                "missing_field n1.seq",
+                "missing_field n1.t",
+                "unknown_field n1.temp",
+                "invalid_field sig.snr",
+                "unknown_field bat",
+                "unknown_field n3",
+                "unknown_field n3.p",
+            ]
+        );
+
+        // Nothing numeric is lost
+        let node = |id: &str| decoded.readings.iter().find(|r| r.node_id == id).unwrap();
+        assert_eq!(node("node1").metric("temp").unwrap().value, 21.5);
+        assert_eq!(node("node3").metric("p").unwrap().value, 1013.2);
+        assert_eq!(node("gateway").metric("bat").unwrap().value, 3.7);
+        assert!(node("node1").link.is_none());
+
+        assert!(decode("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_decode_v2_sequence() {
+        let json = r#"{"v":2,"ts":1,"n1":{"seq":65535,"t":20.0,"h":50.0,"g":1},"n2":{},"sig":{"rssi":-70,"snr":9},"sts":{"rx":1,"err":0}}"#;
+        let decoded = decode(json).unwrap();
+        assert!(decoded.mismatches.is_empty());
+        assert_eq!(decoded.readings[0].seq, Some(65535));
+        // The sequence number is metadata, not a metric
+        assert!(decoded.readings[0].metric("seq").is_none());
+    }
+}